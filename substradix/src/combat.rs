@@ -7,6 +7,11 @@ pub fn combat(mut player_stats: structs::CombatInfo, mut enemy_stats: structs::C
     let mut rounds: u16 = 0;
     let mut hits: u16 = 0;
     let mut enemy_hits: u16 = 0;
+    // Priority divides by enemy speed, and 0 or negative speed would never let a fighter act
+    assert!(player_stats.speed > dec!(0), "Player speed must be positive");
+    assert!(enemy_stats.speed > dec!(0), "Enemy speed must be positive");
+    let player_max_health = player_stats.health;
+    let enemy_max_health = enemy_stats.health;
     // Loop which runs until one of the fighter's health is 0
     'outer: loop {
         rounds += 1;
@@ -27,8 +32,7 @@ pub fn combat(mut player_stats: structs::CombatInfo, mut enemy_stats: structs::C
             info!("Player attacks");
             // Loop which implements the amount of hits the player can do, depending on priority. 
             loop {
-                // Applies random variance to the base damage, and rounds that damage nummber to the nearest integer
-                enemy_stats.health -= Decimal::round(&(damage_given * rng::seed_decimal(75,125,dec!(100))), 0, RoundingMode::TowardsNearestAndHalfTowardsZero); 
+                hit(damage_given, &mut player_stats, &mut enemy_stats, player_max_health);
                 hits += 1;
                 info!("Hits: {}", hits);
                 info!("Health: {} Enemy Health: {}", player_stats.health, enemy_stats.health);
@@ -48,7 +52,7 @@ pub fn combat(mut player_stats: structs::CombatInfo, mut enemy_stats: structs::C
                 // Once priority is below 1, the enemy gets a hit and the round is over.
                 else {
                     info!("Enemy attacks");
-                    player_stats.health -= Decimal::round(&(damage_taken * rng::seed_decimal(75,125,dec!(100))), 0, RoundingMode::TowardsNearestAndHalfTowardsZero);
+                    hit(damage_taken, &mut enemy_stats, &mut player_stats, enemy_max_health);
                     enemy_hits = 1;
                     info!("Enemy hits: {}", enemy_hits);
                     info!("Health: {} Enemy Health: {}", player_stats.health,enemy_stats.health);
@@ -68,7 +72,7 @@ pub fn combat(mut player_stats: structs::CombatInfo, mut enemy_stats: structs::C
             info!("Enemy attacks");
             // Loop which implements the amount of hits the enemy can do
             loop {
-                player_stats.health -= Decimal::round(&(damage_taken * rng::seed_decimal(75,125,dec!(100))), 0, RoundingMode::TowardsNearestAndHalfTowardsZero);
+                hit(damage_taken, &mut enemy_stats, &mut player_stats, enemy_max_health);
                 enemy_hits += 1;
                 info!("Enemy hits: {}", enemy_hits);
                 info!("Health: {} Enemy Health: {}", player_stats.health,enemy_stats.health);
@@ -86,7 +90,7 @@ pub fn combat(mut player_stats: structs::CombatInfo, mut enemy_stats: structs::C
                 }
                 else {
                     info!("Player attacks");
-                    enemy_stats.health -= Decimal::round(&(damage_given * rng::seed_decimal(75,125,dec!(100))), 0, RoundingMode::TowardsNearestAndHalfTowardsZero);
+                    hit(damage_given, &mut player_stats, &mut enemy_stats, player_max_health);
                     hits = 1;
                     info!("Hits: {}", hits);
                     info!("Health: {} Enemy Health: {}", player_stats.health,enemy_stats.health);
@@ -104,3 +108,33 @@ pub fn combat(mut player_stats: structs::CombatInfo, mut enemy_stats: structs::C
         };
     }
 }
+
+
+// Applies random variance to the base damage, and rounds that damage nummber to the nearest integer
+// The attacker heals for their lifesteal % of the damage dealt, up to the health they started the fight with
+fn hit(damage: Decimal, attacker: &mut structs::CombatInfo, defender: &mut structs::CombatInfo, attacker_max_health: Decimal) {
+    let dealt = Decimal::round(&(damage * rng::seed_decimal(75,125,dec!(100))), 0, RoundingMode::TowardsNearestAndHalfTowardsZero);
+    defender.health -= dealt;
+    attacker.health = std::cmp::min(attacker_max_health, attacker.health + dealt * attacker.lifesteal / dec!(100));
+}
+
+// Speed multiplier for a load (gear weight / carry capacity). Interpolated between the points of the curve, and never below the floor
+pub fn encumbrance(load: Decimal, curve: &Vec<(Decimal, Decimal)>, floor: Decimal) -> Decimal {
+    let mut multiplier = match curve.last() {
+        Some(point) => point.1,
+        None => dec!(1),
+    };
+    for pair in curve.windows(2) {
+        let (load_1, multiplier_1) = pair[0];
+        let (load_2, multiplier_2) = pair[1];
+        if load <= load_1 {
+            multiplier = multiplier_1;
+            break;
+        }
+        if load <= load_2 {
+            multiplier = multiplier_1 + (multiplier_2 - multiplier_1) * (load - load_1) / (load_2 - load_1);
+            break;
+        }
+    }
+    std::cmp::max(multiplier, floor)
+}
\ No newline at end of file
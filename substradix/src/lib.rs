@@ -3,6 +3,7 @@ use scrypto::prelude::*;
 mod structs; // has all the structs for objects
 mod rng; // has the pseudo random number generator
 mod combat; // has the combat function
+mod order_book; // has the order book exchange for materials
 
 blueprint! {
     struct Substradix {
@@ -10,19 +11,34 @@ blueprint! {
         character_nft: ResourceAddress, // Resource address of the character NFT
         system_vault: Vault, // Stores the system's badge to approve allowed actions
         developer_vault: Vault, // Stores the developer's badge for later actions
-        gold_vault: Vault, // Stores Gold for the game. Can be spent on items.
+        proceeds_vaults: HashMap<ResourceAddress, Vault>, // Stores marketplace sale proceeds until redeemed, by payment token
         weapon_nft: ResourceAddress, // Resource address of the weapon NFT
         armor_nft: ResourceAddress, // Resource address of the armor NFT
         accessory_nft: ResourceAddress, // Resource address of the accessory NFT
         receipt_nft: ResourceAddress, // Resource address of the receipt NFT
+        bid_nft: ResourceAddress, // Resource address of the auction bid NFT
+        bid_vaults: HashMap<ResourceAddress, Vault>, // Stores tokens escrowed by auction bids
+        fee_vaults: HashMap<ResourceAddress, Vault>, // Stores the treasury share of marketplace sales
+        royalty_vaults: HashMap<ResourceAddress, Vault>, // Stores unclaimed crafter royalties
+        royalties: LazyMap<(NonFungibleId, ResourceAddress), Decimal>, // Unclaimed royalties of each crafter, by character id and token
+        offer_nft: ResourceAddress, // Resource address of the buy offer NFT
+        offer_vaults: HashMap<ResourceAddress, Vault>, // Stores tokens escrowed by buy offers
+        gem_nft: ResourceAddress, // Resource address of the gem NFT
+        skin_nft: ResourceAddress, // Resource address of the skin NFT
+        scroll_nft: ResourceAddress, // Resource address of the recipe scroll NFT
+        skin_vault: Vault, // Stores all Skin NFTs applied to characters or gear
         token_greavite: ResourceAddress, // Resource address of the greavite token
         token_wood: ResourceAddress, // Resource address of the wood token
         token_gold: ResourceAddress, // Resource address of the gold token
+        token_catalyst: ResourceAddress, // Resource address of the fusion catalyst token
+        order_books: HashMap<ResourceAddress, ComponentAddress>, // Order book trading each material for gold
         marketplace_weapon_vault: Vault, // Stores all Weapon NFTs listed on the marketplace
         marketplace_accessory_vault: Vault, // Stores all Accessory NFTs listed on the marketplace
         marketplace_armor_vault: Vault, // Stores all Armor NFTs listed on the marketplace
-        // LazyMaps don't support Clone... or the remove function...
-        marketplace_listings: LazyMap<(structs::Categories, NonFungibleId), (structs::Receipt, bool)>, // Stores data of all listings on the marketplace
+        marketplace_skin_vault: Vault, // Stores all Skin NFTs listed on the marketplace
+        marketplace_listings: HashMap<NonFungibleId, structs::Listing>, // Stores data of unfinished listings on the marketplace, keyed by receipt id
+        sale_history: LazyMap<(structs::Categories, NonFungibleId), Vec<structs::Sale>>, // Every sale of each NFT, oldest first
+        listing_index: Vec<NonFungibleId>, // Keys of all active listings, so they can be enumerated
         game_data: structs::GameData, // All other game data
     }
 
@@ -78,6 +94,46 @@ blueprint! {
                 .restrict_withdraw(AccessRule::AllowAll, MUTABLE(developer_rule.clone()))
                 .updateable_non_fungible_data(system_rule.clone(), MUTABLE(developer_rule.clone()))
                 .no_initial_supply();
+            // Bids on auctions, redeemed for a refund or the won items
+            let bid_nft = ResourceBuilder::new_non_fungible()
+                .metadata("type", "Substradix bid NFT")
+                .mintable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .burnable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .restrict_withdraw(AccessRule::AllowAll, MUTABLE(developer_rule.clone()))
+                .updateable_non_fungible_data(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .no_initial_supply();
+            // Buy offers, redeemed for the item once accepted or for a refund
+            let offer_nft = ResourceBuilder::new_non_fungible()
+                .metadata("type", "Substradix offer NFT")
+                .mintable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .burnable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .restrict_withdraw(AccessRule::AllowAll, MUTABLE(developer_rule.clone()))
+                .updateable_non_fungible_data(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .no_initial_supply();
+            // Gems which can be socketed into gear
+            let gem_nft = ResourceBuilder::new_non_fungible()
+                .metadata("type", "Substradix gem NFT")
+                .mintable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .burnable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .restrict_withdraw(AccessRule::AllowAll, MUTABLE(developer_rule.clone()))
+                .updateable_non_fungible_data(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .no_initial_supply();
+            // Cosmetic skins for characters and gear
+            let skin_nft = ResourceBuilder::new_non_fungible()
+                .metadata("type", "Substradix skin NFT")
+                .mintable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .burnable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .restrict_withdraw(AccessRule::AllowAll, MUTABLE(developer_rule.clone()))
+                .updateable_non_fungible_data(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .no_initial_supply();
+            // Scrolls for recipes that need one, dropped by enemies
+            let scroll_nft = ResourceBuilder::new_non_fungible()
+                .metadata("type", "Substradix recipe scroll NFT")
+                .mintable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .burnable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .restrict_withdraw(AccessRule::AllowAll, MUTABLE(developer_rule.clone()))
+                .updateable_non_fungible_data(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .no_initial_supply();
             // Gold for ingame currency
             let token_gold = ResourceBuilder::new_fungible()
                 .metadata("name", "Gold Coin")
@@ -95,6 +151,18 @@ blueprint! {
                 .mintable(rule!(require(system_badge.resource_address())), MUTABLE(developer_rule.clone()))
                 .burnable(rule!(require(system_badge.resource_address())), MUTABLE(developer_rule.clone()))
                 .no_initial_supply();
+            // Rare drop from salvaging gear
+            let token_catalyst = ResourceBuilder::new_fungible()
+                .metadata("name", "Fusion Catalyst")
+                .divisibility(DIVISIBILITY_NONE)
+                .mintable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .burnable(system_rule.clone(), MUTABLE(developer_rule.clone()))
+                .no_initial_supply();
+
+            // Exchanges for trading crafting materials with gold
+            let mut order_books = HashMap::new();
+            order_books.insert(token_greavite, order_book::OrderBook::new(token_greavite, token_gold));
+            order_books.insert(token_wood, order_book::OrderBook::new(token_wood, token_gold));
 
             // Game Data can be hardcoded at instantiation, but I had it be set through transaction manifest for future use.
             // Game Data can always be updated through the Transaction Manifest, example file is setup.rtm.
@@ -112,6 +180,41 @@ blueprint! {
                 weapon_data: HashMap::new(),
                 armor_data: HashMap::new(),
                 accessory_data: HashMap::new(),
+                recipes: HashMap::new(),
+                rarity_data: Vec::new(),
+                affix_pool: HashMap::new(),
+                durability_data: structs::DurabilityData {
+                    max_durability: 100,
+                    broken_multiplier: dec!(".5"),
+                    gold_per_point: dec!(".1"),
+                    greavite_per_point: dec!(".1"),
+                },
+                salvage_data: HashMap::new(),
+                fusion_data: HashMap::new(),
+                gem_data: HashMap::new(),
+                socket_data: structs::SocketData {
+                    levels_per_socket: 2,
+                    max_sockets: 3,
+                },
+                set_data: HashMap::new(),
+                // Same as the old penalty of 1% speed per 0.01 weight, but never below 10% speed
+                encumbrance_data: structs::EncumbranceData {
+                    base_capacity: dec!(1),
+                    capacity_per_level: dec!(0),
+                    curve: vec![(dec!(0), dec!(1)), (dec!(1), dec!(0))],
+                    floor: dec!(".1"),
+                },
+                achievement_data: HashMap::new(),
+                quest_data: HashMap::new(),
+                loot_tables: HashMap::new(),
+                auction_extension: 1,
+                // Same as the old fixed 5% burn
+                market_fees: structs::MarketFees {
+                    burn_share: dec!(".05"),
+                    treasury_share: dec!(0),
+                    royalty_share: dec!(0),
+                },
+                payment_tokens: vec![token_gold, RADIX_TOKEN, token_greavite, token_wood],
             };
 
             let instantiate = Self {
@@ -119,19 +222,35 @@ blueprint! {
                 // Vault holds all Developer badeges except one given to instantiator.
                 developer_vault: Vault::with_bucket(developer_badge.take(9999)),
                 collected_xrd: Vault::new(RADIX_TOKEN),
-                gold_vault: Vault::new(token_gold),
+                proceeds_vaults: HashMap::new(),
                 character_nft,
                 weapon_nft,
                 armor_nft,
                 accessory_nft,
                 receipt_nft,
+                bid_nft,
+                bid_vaults: HashMap::new(),
+                fee_vaults: HashMap::new(),
+                royalty_vaults: HashMap::new(),
+                royalties: LazyMap::new(),
+                offer_nft,
+                offer_vaults: HashMap::new(),
+                gem_nft,
+                skin_nft,
+                scroll_nft,
+                skin_vault: Vault::new(skin_nft),
                 token_greavite,
                 token_wood,
                 token_gold,
+                token_catalyst,
+                order_books,
                 marketplace_weapon_vault: Vault::new(weapon_nft),
                 marketplace_armor_vault: Vault::new(armor_nft),
                 marketplace_accessory_vault: Vault::new(accessory_nft),
-                marketplace_listings: LazyMap::new(),
+                marketplace_skin_vault: Vault::new(skin_nft),
+                marketplace_listings: HashMap::new(),
+                sale_history: LazyMap::new(),
+                listing_index: Vec::new(),
                 game_data,
             }
             .instantiate();
@@ -139,24 +258,64 @@ blueprint! {
             // Sets access for various methods
             let access_rules = AccessRules::new()
                 .method("withdraw_xrd", rule!(require(developer_badge.resource_address())))
+                .method("withdraw_fees", rule!(require(developer_badge.resource_address())))
+                .method("upload_market_fees", rule!(require(developer_badge.resource_address())))
+                .method("upload_payment_tokens", rule!(require(developer_badge.resource_address())))
                 .method("upload_stage_data", rule!(require(developer_badge.resource_address())))
                 .method("upload_char_data", rule!(require(developer_badge.resource_address())))
                 .method("change_price", rule!(require(developer_badge.resource_address())))
+                .method("change_auction_extension", rule!(require(developer_badge.resource_address())))
                 .method("upload_levelup_data", rule!(require(developer_badge.resource_address())))
                 .method("upload_weapon_data", rule!(require(developer_badge.resource_address())))
                 .method("upload_armor_data", rule!(require(developer_badge.resource_address())))
                 .method("upload_accessory_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_recipe", rule!(require(developer_badge.resource_address())))
+                .method("upload_rarity_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_affix_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_durability_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_salvage_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_fusion_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_gem_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_socket_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_set_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_encumbrance_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_achievement_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_quest_data", rule!(require(developer_badge.resource_address())))
+                .method("upload_loot_table", rule!(require(developer_badge.resource_address())))
+                .method("mint_skin", rule!(require(developer_badge.resource_address())))
                 .method("create_character", rule!(allow_all))
                 .method("fuse_items", rule!(allow_all))
-                .method("create_weapon", rule!(allow_all))
-                .method("create_armor", rule!(allow_all))
-                .method("create_accessory", rule!(allow_all))
+                .method("batch_fuse", rule!(allow_all))
+                .method("repair", rule!(allow_all))
+                .method("salvage", rule!(allow_all))
+                .method("socket_gem", rule!(allow_all))
+                .method("unsocket_gem", rule!(allow_all))
+                .method("apply_skin", rule!(allow_all))
+                .method("remove_skin", rule!(allow_all))
+                .method("claim_achievement", rule!(allow_all))
+                .method("claim_quest", rule!(allow_all))
+                .method("craft", rule!(allow_all))
                 .method("stage", rule!(allow_all))
                 .method("combat", rule!(allow_all))
                 .method("list_single_gear", rule!(allow_all))
+                .method("list_bundle", rule!(allow_all))
+                .method("list_auction", rule!(allow_all))
+                .method("list_dutch_auction", rule!(allow_all))
+                .method("bid", rule!(allow_all))
+                .method("settle_auction", rule!(allow_all))
+                .method("redeem_bid", rule!(allow_all))
+                .method("make_offer", rule!(allow_all))
+                .method("accept_offer", rule!(allow_all))
+                .method("redeem_offer", rule!(allow_all))
                 .method("buy_single_gear", rule!(allow_all))
                 .method("redeem_receipt", rule!(allow_all))
                 .method("change_listing_price", rule!(allow_all))
+                .method("get_listings", rule!(allow_all))
+                .method("get_listing", rule!(allow_all))
+                .method("get_sale_history", rule!(allow_all))
+                .method("sweep_expired", rule!(allow_all))
+                .method("claim_royalties", rule!(allow_all))
+                .method("get_order_book", rule!(allow_all))
                 .method("remove_listing", rule!(allow_all))
                 .method("levelup", rule!(allow_all));
             
@@ -167,10 +326,31 @@ blueprint! {
         pub fn withdraw_xrd(&mut self) -> Bucket {
             self.collected_xrd.take_all()
         }
+        // Dev only, collects the treasury share of marketplace sales paid in a token
+        pub fn withdraw_fees(&mut self, token: ResourceAddress) -> Bucket {
+            Self::token_vault(&mut self.fee_vaults, token).take_all()
+        }
+        pub fn upload_market_fees(&mut self, fees: structs::MarketFees) {
+            assert!(fees.burn_share >= dec!(0) && fees.treasury_share >= dec!(0) && fees.royalty_share >= dec!(0), "Fee shares can't be negative");
+            assert!(fees.burn_share + fees.treasury_share + fees.royalty_share <= dec!(1), "Fee shares can't add up to more than the price");
+            self.game_data.market_fees = fees;
+        }
+        // Sets the tokens new listings and offers can be priced in, out of Gold, Greavite, Wood and XRD. Existing listings keep their token.
+        // Sales burn part of the price, which the system badge can only do for the game's own tokens. XRD burns go to the treasury instead
+        pub fn upload_payment_tokens(&mut self, tokens: Vec<ResourceAddress>) {
+            assert!(!tokens.is_empty(), "At least one payment token must be accepted");
+            let allowed = vec![self.token_gold, self.token_greavite, self.token_wood, RADIX_TOKEN];
+            assert!(tokens.iter().all(|x| allowed.contains(x)), "Payment tokens must be Gold, Greavite, Wood or XRD");
+            self.game_data.payment_tokens = tokens;
+        }
         // Changes price of Substradix
         pub fn change_price(&mut self, new_price: Decimal) {
             self.game_data.game_price = new_price;
         }
+        // Changes how many epochs a late bid keeps an auction open for
+        pub fn change_auction_extension(&mut self, epochs: u64) {
+            self.game_data.auction_extension = epochs;
+        }
         // Upload data for the game
         pub fn upload_levelup_data(&mut self, data: Vec<u128>) {
             self.game_data.exp_data = data;
@@ -185,6 +365,85 @@ blueprint! {
         pub fn upload_accessory_data(&mut self, accessory: structs::AccessoryNames,  id: Decimal, data: structs::AccessoryData) {
             self.game_data.accessory_data.insert((accessory, id), data);
         }
+        pub fn upload_recipe(&mut self, recipe_id: u64, recipe: structs::Recipe) {
+            assert!(recipe.min_stat_roll < recipe.max_stat_roll, "Min stat roll must be below the max stat roll");
+            self.game_data.recipes.insert(recipe_id, recipe);
+        }
+        // Rarity tiers rolled when crafting, from common to legendary
+        pub fn upload_rarity_data(&mut self, data: Vec<structs::RarityData>) {
+            assert!(!data.is_empty(), "At least one rarity tier is needed");
+            assert!(data.iter().map(|x| x.weight).sum::<u128>() > 0, "Rarity weights can't all be zero");
+            self.game_data.rarity_data = data;
+        }
+        // Prefixes and suffixes which can be rolled onto gear
+        pub fn upload_affix_data(&mut self, affix_id: u64, data: structs::AffixData) {
+            assert!(!data.tiers.is_empty(), "At least one affix tier is needed");
+            assert!(data.tiers.iter().all(|x| x.0 <= x.1), "Affix tier min can't be above max");
+            self.game_data.affix_pool.insert(affix_id, data);
+        }
+        pub fn upload_durability_data(&mut self, data: structs::DurabilityData) {
+            self.game_data.durability_data = data;
+        }
+        pub fn upload_salvage_data(&mut self, category: structs::Categories, data: structs::SalvageData) {
+            self.game_data.salvage_data.insert(category, data);
+        }
+        // Fusion odds, multipliers and costs for fusing two items of the given level
+        pub fn upload_fusion_data(&mut self, level: u64, data: structs::FusionData) {
+            assert!(data.min_multiplier > 0, "Min multiplier must be positive");
+            assert!(data.min_multiplier < data.max_multiplier, "Min multiplier must be below the max multiplier");
+            assert!(data.success_odds <= 100, "Success odds are a % chance");
+            self.game_data.fusion_data.insert(level, data);
+        }
+        // Gems are crafted through recipes with an ItemTemplate::Gem output
+        pub fn upload_gem_data(&mut self, id: Decimal, data: structs::GemData) {
+            self.game_data.gem_data.insert(id, data);
+        }
+        pub fn upload_socket_data(&mut self, data: structs::SocketData) {
+            assert!(data.levels_per_socket > 0, "levels_per_socket must be positive");
+            self.game_data.socket_data = data;
+        }
+        // Gear sets, giving bonuses for wearing multiple pieces of the set
+        pub fn upload_set_data(&mut self, set_id: u64, data: structs::SetData) {
+            self.game_data.set_data.insert(set_id, data);
+        }
+        pub fn upload_encumbrance_data(&mut self, data: structs::EncumbranceData) {
+            assert!(data.base_capacity > dec!(0), "Base carry capacity must be positive");
+            assert!(data.capacity_per_level >= dec!(0), "Carry capacity per level can't be negative");
+            assert!(data.floor > dec!(0), "Encumbrance floor must be positive, or speed can reach 0");
+            assert!(!data.curve.is_empty(), "Encumbrance curve needs at least one point");
+            for pair in data.curve.windows(2) {
+                assert!(pair[0].0 < pair[1].0, "Encumbrance curve must be sorted by load");
+            }
+            self.game_data.encumbrance_data = data;
+        }
+        // Achievements unlock a title on the character, and may have one-time rewards
+        pub fn upload_achievement_data(&mut self, achievement_id: u64, data: structs::AchievementData) {
+            self.game_data.achievement_data.insert(achievement_id, data);
+        }
+        // Adds or replaces a quest on the quest board
+        pub fn upload_quest_data(&mut self, quest_id: u64, data: structs::QuestData) {
+            assert!(data.reset_epochs > 0, "Quests must reset after at least 1 epoch");
+            assert!(data.amount > 0, "Quests need an amount of at least 1");
+            self.game_data.quest_data.insert(quest_id, data);
+        }
+        // Loot table of an enemy, enemy being 0, 1 or 2 in the order they are fought
+        pub fn upload_loot_table(&mut self, stage: u64, enemy: u64, data: structs::LootTable) {
+            assert!(enemy < 3, "Stages have 3 enemies");
+            for entry in data.on_win.iter().chain(data.on_loss.iter()) {
+                assert!(entry.min <= entry.max, "Loot min can't be above max");
+                assert!(entry.odds <= 10000, "Loot odds are out of 10000");
+                if let structs::Loot::Item(_, min_roll, max_roll) = entry.loot {
+                    assert!(min_roll < max_roll, "Min stat roll must be below the max stat roll");
+                }
+            }
+            self.game_data.loot_tables.insert((stage, enemy), data);
+        }
+        // Dev only, mints cosmetic skins for events, rewards or sale
+        pub fn mint_skin(&mut self, data: structs::Skin) -> Bucket {
+            self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.skin_nft)
+                    .mint_non_fungible(&NonFungibleId::random(), data))
+        }
         pub fn upload_stage_data(&mut self, stage_number: u64, enemy1_data: structs::EnemyFullInfo, enemy2_data: structs::EnemyFullInfo, enemy3_data: structs::EnemyFullInfo,) {		                       			
             self.game_data.stage_data.insert(stage_number, vec![enemy1_data, enemy2_data, enemy3_data]);
         }
@@ -206,7 +465,7 @@ blueprint! {
             let mag = self.game_data.char_mag.get(&class).unwrap();
             let def = self.game_data.char_def.get(&class).unwrap();
             let spd = self.game_data.char_spd.get(&class).unwrap();
-            let character_data = structs::Character { 
+            let mut character_data = structs::Character { 
                 name: name, 
                 class: class, 
                 level: 1, 
@@ -218,7 +477,15 @@ blueprint! {
                 defense: def[0].into(), 
                 speed: spd[0].into(), 
                 version: self.game_data.game_version, 
+                skin: None,
+                titles: Vec::new(),
+                counters: HashMap::new(),
+                achievements: Vec::new(),
+                claimed_achievements: Vec::new(),
+                quests: HashMap::new(),
             };
+            // Unlocks starting achievements, such as reaching level 1
+            self.unlock_achievements(&mut character_data);
             ComponentAuthZone::push(self.system_vault.create_proof());
 
             let new_character = borrow_resource_manager!(self.character_nft)
@@ -230,330 +497,1165 @@ blueprint! {
             self.collected_xrd.put(payment.take(self.game_data.game_price));
             return (new_character, payment,)
         }
-        // Takes two of the same type + level Weapon/Armor/Accessory NFT, burns them, and makes a new one based off of the first. 
-        // Takes stats from first NFT. Stats increase by 20% per upgrade
-        pub fn fuse_items(&mut self, item_bucket: Bucket) -> Bucket {
+        // Takes two of the same type + level Weapon/Armor/Accessory NFT and fuses them, using the fusion data of their level.
+        // Catalysts raise the success odds, up to the max for the level. Returns the fused item(s), and leftover Gold and catalysts
+        pub fn fuse_items(&mut self, character: Proof, item_bucket: Bucket, mut gold: Bucket, mut catalyst: Bucket) -> (Bucket, Bucket, Bucket) {
             assert!(item_bucket.amount() == dec!("2"));
             assert!(item_bucket.resource_address() == self.weapon_nft || 
             item_bucket.resource_address() == self.armor_nft ||
             item_bucket.resource_address() == self.accessory_nft);
-            if item_bucket.resource_address() == self.weapon_nft {
-                let mut item: structs::Weapon = item_bucket.non_fungibles()[0].data();
-                let item2: structs::Weapon = item_bucket.non_fungibles()[1].data();
-                // Makes sure items are same level + type
-                assert!(item.item_info.level == item2.item_info.level);
-                assert!(item.item_info.id == item2.item_info.id);
-                // Sets new item stats
-                item.item_info.level += 1;
-                item.physical_base *= dec!("1.2");
-                item.physical_scaling *= dec!("1.2");
-                item.spell_base *= dec!("1.2");
-                item.spell_scaling *= dec!("1.2"); 
-                let new_info = structs::Item {
-                    id: item.item_info.id,
-                    version: self.game_data.game_version,
-                    weight: item.item_info.weight,
-                    level: item.item_info.level, 
-                };         
-                let new = structs::Weapon { class: item.class, physical_base: item.physical_base, item_info: new_info, 
-                    ability: item.ability, ability_odds: item.ability_odds,
-                    physical_scaling: item.physical_scaling, spell_base: item.spell_base, spell_scaling: item.spell_scaling, range: item.range, 
-                };
+            assert!(gold.resource_address() == self.token_gold);
+            assert!(catalyst.resource_address() == self.token_catalyst);
+            let id = item_bucket.non_fungible_ids().into_iter().next().unwrap();
+            let level = self.get_item_info(item_bucket.resource_address(), &id).level;
+            let max_catalysts: Decimal = self.game_data.fusion_data.get(&level).unwrap().max_catalysts.into();
+            let used_catalysts = catalyst.take(std::cmp::min(catalyst.amount(), max_catalysts));
+            let (new_bucket, success) = self.fuse_pair(item_bucket, &mut gold, used_catalysts.amount());
+            if success {
+                self.record_for(&character, structs::Task::Fuse, 1);
+            }
+            self.system_vault.authorize(|| 
+                used_catalysts.burn());
+            (new_bucket, gold, catalyst)
+        }
+        // Takes many same-id Weapon/Armor/Accessory NFTs and fuses pairs from the lowest level upwards, until no pair below target_level is left.
+        // Only items of the same weapon class or gear part are paired. Stops early when Gold runs out.
+        // Items with gems or a skin, or without a partner, are never fused, and are returned as leftovers.
+        // Returns the fused items plus leftovers, and leftover Gold
+        pub fn batch_fuse(&mut self, character: Proof, mut items: Bucket, mut gold: Bucket, target_level: u64) -> (Bucket, Bucket) {
+            assert!(items.resource_address() == self.weapon_nft || 
+            items.resource_address() == self.armor_nft ||
+            items.resource_address() == self.accessory_nft);
+            assert!(gold.resource_address() == self.token_gold);
+            assert!(!items.is_empty(), "No items to fuse");
+            let resource = items.resource_address();
+            let first_id = items.non_fungible_ids().into_iter().next().unwrap();
+            let item_id = self.get_item_info(resource, &first_id).id;
+            let mut fusions = 0;
+            loop {
+                // Sorts the items by level and fusion group
+                let mut by_level: HashMap<(u64, structs::FusionGroup), Vec<NonFungibleId>> = HashMap::new();
+                for id in items.non_fungible_ids() {
+                    let info = self.get_item_info(resource, &id);
+                    assert!(info.id == item_id, "All items must have the same item id");
+                    if !info.sockets.is_empty() || info.skin.is_some() {
+                        continue;
+                    }
+                    by_level.entry((info.level, self.fusion_group(resource, &id))).or_insert(Vec::new()).push(id);
+                }
+                let mut groups: Vec<(u64, structs::FusionGroup)> = by_level.keys().cloned().collect();
+                groups.sort_by_key(|x| x.0);
+                // Lowest level with a pair that can still be fused and paid for
+                let next = groups.into_iter().find(|group| {
+                    group.0 < target_level && by_level[group].len() >= 2 && match self.game_data.fusion_data.get(&group.0) {
+                        Some(fusion_info) => gold.amount() >= fusion_info.gold_cost,
+                        None => false,
+                    }
+                });
+                match next {
+                    Some(group) => {
+                        let ids = &by_level[&group];
+                        let mut pair = items.take_non_fungible(&ids[0]);
+                        pair.put(items.take_non_fungible(&ids[1]));
+                        let (fused, success) = self.fuse_pair(pair, &mut gold, dec!(0));
+                        if success {
+                            fusions += 1;
+                        }
+                        items.put(fused);
+                    }
+                    None => break,
+                }
+            }
+            self.record_for(&character, structs::Task::Fuse, fusions);
+            (items, gold)
+        }
+        // Weapon class or gear part of a Weapon/Armor/Accessory NFT
+        fn fusion_group(&self, resource: ResourceAddress, id: &NonFungibleId) -> structs::FusionGroup {
+            let resource_manager = borrow_resource_manager!(resource);
+            if resource == self.weapon_nft {
+                structs::FusionGroup::Weapon(resource_manager.get_non_fungible_data::<structs::Weapon>(id).class)
+            }
+            else if resource == self.armor_nft {
+                structs::FusionGroup::Armor(resource_manager.get_non_fungible_data::<structs::Armor>(id).part)
+            }
+            else {
+                structs::FusionGroup::Accessory(resource_manager.get_non_fungible_data::<structs::Accessory>(id).part)
+            }
+        }
+        // Fuses two items of the same type + level. The Gold cost is always burned, then the fusion either succeeds or has the failure outcome of the level.
+        // On success, stats of both items are averaged and multiplied, affixes are re-rolled at the new level, and durability is restored.
+        // Returns the item(s) left, and whether the fusion succeeded
+        fn fuse_pair(&mut self, mut pair: Bucket, gold: &mut Bucket, catalysts: Decimal) -> (Bucket, bool) {
+            let resource = pair.resource_address();
+            let ids: Vec<NonFungibleId> = pair.non_fungible_ids().into_iter().collect();
+            let info = self.get_item_info(resource, &ids[0]);
+            let info2 = self.get_item_info(resource, &ids[1]);
+            // Makes sure items are same level + type
+            assert!(info.level == info2.level);
+            assert!(info.id == info2.id);
+            // Gems and skins must be removed first, so they are not lost
+            assert!(info.sockets.is_empty() && info2.sockets.is_empty());
+            assert!(info.skin.is_none() && info2.skin.is_none());
+            let fusion_info = self.game_data.fusion_data.get(&info.level).unwrap().clone();
+            let gold_bucket: Bucket = gold.take(fusion_info.gold_cost);
+            let success_odds: Decimal = (fusion_info.success_odds as i128).into();
+            let catalyst_bonus: Decimal = (fusion_info.catalyst_bonus as i128).into();
+            let success = rng::seed_decimal(0, 100, dec!(1)) < success_odds + catalyst_bonus * catalysts;
+            let (level, multiplier) = if success {
+                (info.level + 1, rng::seed_decimal(fusion_info.min_multiplier, fusion_info.max_multiplier, dec!(100)))
+            }
+            else {
+                match fusion_info.failure {
+                    structs::FusionFailure::LoseOne => {
+                        let lost = pair.take_non_fungible(&ids[rng::seed(0, 2) as usize]);
 
-                ComponentAuthZone::push(self.system_vault.create_proof());
+                        ComponentAuthZone::push(self.system_vault.create_proof());
 
-                item_bucket.burn();
-                let new_bucket = borrow_resource_manager!(self.weapon_nft)
-                        .mint_non_fungible(&NonFungibleId::random(), new);
-                        
-                ComponentAuthZone::pop();
-                return new_bucket
-            }
-            else if item_bucket.resource_address() == self.armor_nft {
-                let mut item: structs::Armor = item_bucket.non_fungibles()[0].data();
-                let item2: structs::Armor = item_bucket.non_fungibles()[1].data();   
-                assert!(item.item_info.level == item2.item_info.level);           
-                assert!(item.item_info.id == item2.item_info.id);
-                assert!(item.part == item2.part);
-                item.item_info.level += 1; 
-                item.health *= dec!("1.25");
-                item.defense *= dec!("1.25");
-                let new_info = structs::Item {
-                    id: item.item_info.id,
-                    version: self.game_data.game_version,
-                    weight: item.item_info.weight,
-                    level: item.item_info.level, 
-                };   
-                let new = structs::Armor { part: item.part, health: item.health, defense: item.defense, item_info: new_info
-                };
+                        lost.burn();
+                        gold_bucket.burn();
 
-                ComponentAuthZone::push(self.system_vault.create_proof());
+                        ComponentAuthZone::pop();
 
-                item_bucket.burn();
-                let new_bucket = borrow_resource_manager!(self.armor_nft)
-                        .mint_non_fungible(&NonFungibleId::random(), new);
+                        return (pair, false)
+                    }
+                    // Undoes the weakest possible upgrade
+                    structs::FusionFailure::Downgrade => {
+                        let min_multiplier: Decimal = (fusion_info.min_multiplier as i128).into();
+                        (std::cmp::max(info.level - 1, 1), dec!(100) / min_multiplier)
+                    }
+                }
+            };
+            let new_info = structs::Item {
+                version: self.game_data.game_version,
+                level: level,
+                affixes: self.roll_affixes(info.affix_slots, level),
+                durability: info.max_durability,
+                ..info.clone()
+            };
 
-                ComponentAuthZone::pop();
-                
-                return new_bucket
+            ComponentAuthZone::push(self.system_vault.create_proof());
+
+            let new_bucket = if resource == self.weapon_nft {
+                let item: structs::Weapon = borrow_resource_manager!(resource).get_non_fungible_data(&ids[0]);
+                let item2: structs::Weapon = borrow_resource_manager!(resource).get_non_fungible_data(&ids[1]);
+                assert!(item.class == item2.class);
+                let mut new = item.blend(&item2, multiplier);
+                new.item_info = new_info;
+                borrow_resource_manager!(resource).mint_non_fungible(&NonFungibleId::random(), new)
+            }
+            else if resource == self.armor_nft {
+                let item: structs::Armor = borrow_resource_manager!(resource).get_non_fungible_data(&ids[0]);
+                let item2: structs::Armor = borrow_resource_manager!(resource).get_non_fungible_data(&ids[1]);
+                assert!(item.part == item2.part);
+                let mut new = item.blend(&item2, multiplier);
+                new.item_info = new_info;
+                borrow_resource_manager!(resource).mint_non_fungible(&NonFungibleId::random(), new)
             }
             else {
-                let mut item: structs::Accessory = item_bucket.non_fungibles()[0].data();
-                let item2: structs::Accessory = item_bucket.non_fungibles()[1].data();  
-                assert!(item.item_info.level == item2.item_info.level);         
-                assert!(item.item_info.id == item2.item_info.id);
+                let item: structs::Accessory = borrow_resource_manager!(resource).get_non_fungible_data(&ids[0]);
+                let item2: structs::Accessory = borrow_resource_manager!(resource).get_non_fungible_data(&ids[1]);
                 assert!(item.part == item2.part);
-                item.item_info.level += 1;
-                item.attack *= dec!("1.2");
-                item.magic *= dec!("1.2");
-                item.speed *= dec!("1.2");
-                let new_info = structs::Item {
-                    id: item.item_info.id,
-                    version: self.game_data.game_version,
-                    weight: item.item_info.weight,
-                    level: item.item_info.level, 
-                };   
-                let new = structs::Accessory { 
-                    part: item.part, 
-                    attack: item.attack, 
-                    magic: item.magic, 
-                    speed: item.speed,
-                    ability: item.ability, 
-                    ability_odds: item.ability_odds, 
-                    item_info: new_info
-                };
+                let mut new = item.blend(&item2, multiplier);
+                new.item_info = new_info;
+                borrow_resource_manager!(resource).mint_non_fungible(&NonFungibleId::random(), new)
+            };
+            pair.burn();
+            gold_bucket.burn();
+
+            ComponentAuthZone::pop();
+
+            (new_bucket, success)
+        }
+        // Crafts the item of a recipe. Inputs can be given in any order, and change is returned for every input bucket.
+        // Recipes which need a scroll take it from the inputs as well
+        pub fn craft(&mut self, character: Proof, recipe_id: u64, mut inputs: Vec<Bucket>) -> (Bucket, Vec<Bucket>) {
+            let recipe = self.game_data.recipes.get(&recipe_id).unwrap().clone();
+            // Takes every input and catalyst from the matching bucket. Assertations so no cheating
+            let mut used: Vec<Bucket> = Vec::new();
+            for (resource, amount) in recipe.inputs.iter() {
+                let bucket = inputs.iter_mut().find(|x| x.resource_address() == *resource);
+                assert!(bucket.is_some());
+                used.push(bucket.unwrap().take(*amount));
+            }
+            for catalyst in recipe.catalysts.iter() {
+                let bucket = inputs.iter_mut().find(|x| x.resource_address() == *catalyst);
+                assert!(bucket.is_some());
+                used.push(bucket.unwrap().take(1));
+            }
+            if recipe.needs_scroll {
+                let scroll_nft = self.scroll_nft;
+                let bucket = inputs.iter_mut().find(|x| x.resource_address() == scroll_nft);
+                assert!(bucket.is_some(), "Recipe needs a scroll");
+                let bucket = bucket.unwrap();
+                let scroll = bucket.non_fungibles::<structs::RecipeScroll>().into_iter()
+                    .find(|x| x.data().recipe_id == recipe_id);
+                assert!(scroll.is_some(), "No scroll of the recipe");
+                used.push(bucket.take_non_fungible(&scroll.unwrap().id()));
+            }
+            assert!(character.resource_address() == self.character_nft);
+            let crafter = character.non_fungible_ids().into_iter().next().unwrap();
+            let new_item = self.mint_item(recipe.output, recipe.min_stat_roll, recipe.max_stat_roll, Some(crafter));
 
-                ComponentAuthZone::push(self.system_vault.create_proof());
+            ComponentAuthZone::push(self.system_vault.create_proof());
 
-                item_bucket.burn();
-                let new_bucket = borrow_resource_manager!(self.accessory_nft)
-                        .mint_non_fungible(&NonFungibleId::random(), new);
+            for bucket in used {
+                bucket.burn();
+            }
 
-                ComponentAuthZone::pop();
+            ComponentAuthZone::pop();
 
-                return new_bucket
-            }              
+            let task = match recipe.output {
+                structs::ItemTemplate::Weapon(_, _) => structs::Task::CraftWeapon,
+                structs::ItemTemplate::Armor(part, _) => structs::Task::CraftArmor(part),
+                structs::ItemTemplate::Accessory(part, _) => structs::Task::CraftAccessory(part),
+                structs::ItemTemplate::Gem(_) => structs::Task::CraftGem,
+            };
+            self.record_for(&character, task, 1);
+            (new_item, inputs)
+        }
+        // Mints a new Weapon/Armor/Accessory from its data table. Stats are rolled between min_roll/100 and max_roll/100
+        fn mint_item(&self, template: structs::ItemTemplate, min_roll: u128, max_roll: u128, crafter: Option<NonFungibleId>) -> Bucket {
+            let tier = self.roll_rarity();
+            match template {
+                structs::ItemTemplate::Weapon(class, id) => {
+                    let weapon_info = self.game_data.weapon_data.get(&(class,id)).unwrap();
+                    let seed = rng::seed(weapon_info.min_rng_roll, weapon_info.max_rng_roll);
+                    let ability = if seed == weapon_info.success_number || seed >= weapon_info.success_number {
+                        weapon_info.ability_if_success
+                    }
+                    else {
+                        dec!(0)
+                    };
+                    let info = structs::Item {
+                        id: id,
+                        version: self.game_data.game_version,
+                        weight: weapon_info.weight * rng::seed_decimal(75,125,dec!(100)),
+                        level: 1, 
+                        rarity: tier.rarity,
+                        affix_slots: tier.affix_slots,
+                        affixes: self.roll_affixes(tier.affix_slots, 1),
+                        durability: self.game_data.durability_data.max_durability,
+                        max_durability: self.game_data.durability_data.max_durability,
+                        sockets: Vec::new(),
+                        skin: None,
+                        crafter: crafter.clone(),
+                    };  
+                    let weapon_data = structs::Weapon {  
+                        class: class,
+                        item_info: info,
+                        physical_base: weapon_info.physical_base * tier.stat_multiplier * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        physical_scaling: weapon_info.physical_scaling * tier.stat_multiplier * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        spell_base: weapon_info.magic_base * tier.stat_multiplier * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        spell_scaling: weapon_info.magic_scaling * tier.stat_multiplier * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        ability: ability,
+                        ability_odds: weapon_info.ability_active_odds * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        range: weapon_info.range,
+                    };
+                    self.system_vault.authorize(|| 
+                        borrow_resource_manager!(self.weapon_nft)
+                            .mint_non_fungible(&NonFungibleId::random(), weapon_data))
+                }
+                structs::ItemTemplate::Armor(armor, id) => {
+                    let armor_info = self.game_data.armor_data.get(&(armor,id)).unwrap();
+                    let chance = rng::seed(1, 2);
+                    let health = if chance == 1 { armor_info.health_bonus }
+                    else { armor_info.health };
+                    let defense = if chance == 2 { armor_info.defense_bonus }
+                    else { armor_info.defense };
+                    let info = structs::Item {
+                        id: id,
+                        version: self.game_data.game_version,
+                        weight: armor_info.weight * rng::seed_decimal(75,125,dec!(100)),
+                        level: 1, 
+                        rarity: tier.rarity,
+                        affix_slots: tier.affix_slots,
+                        affixes: self.roll_affixes(tier.affix_slots, 1),
+                        durability: self.game_data.durability_data.max_durability,
+                        max_durability: self.game_data.durability_data.max_durability,
+                        sockets: Vec::new(),
+                        skin: None,
+                        crafter: crafter.clone(),
+                    };  
+                    let armor_data = structs::Armor {  
+                        item_info: info,
+                        part: armor,
+                        health: health * tier.stat_multiplier * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        defense: (dec!(1) + (defense - dec!(1)) * tier.stat_multiplier) * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                    };
+                    self.system_vault.authorize(|| 
+                        borrow_resource_manager!(self.armor_nft)
+                            .mint_non_fungible(&NonFungibleId::random(), armor_data))
+                }
+                structs::ItemTemplate::Accessory(accessory, id) => {
+                    let accessory_info = self.game_data.accessory_data.get(&(accessory,id)).unwrap();
+                    let seed = rng::seed(accessory_info.min_rng_roll, accessory_info.max_rng_roll);
+                    let ability = if seed == accessory_info.success_number || seed >= accessory_info.success_number {
+                        accessory_info.ability_if_success
+                    }
+                    else {
+                        dec!(0)
+                    };
+                    let chance = rng::seed(1, 3);
+                    let attack = if chance == 1 { accessory_info.attack_bonus }
+                    else { accessory_info.attack };
+                    let magic = if chance == 2 { accessory_info.magic_bonus }
+                    else { accessory_info.magic };
+                    let speed = if chance == 3 { accessory_info.speed_bonus }
+                    else { accessory_info.speed };
+                    let info = structs::Item {
+                        id: id,
+                        version: self.game_data.game_version,
+                        weight: accessory_info.weight * rng::seed_decimal(75,125,dec!(100)),
+                        level: 1, 
+                        rarity: tier.rarity,
+                        affix_slots: tier.affix_slots,
+                        affixes: self.roll_affixes(tier.affix_slots, 1),
+                        durability: self.game_data.durability_data.max_durability,
+                        max_durability: self.game_data.durability_data.max_durability,
+                        sockets: Vec::new(),
+                        skin: None,
+                        crafter: crafter.clone(),
+                    };  
+                    let accessory_data = structs::Accessory {  
+                        item_info: info,
+                        part: accessory,
+                        attack: (dec!(1) + (attack - dec!(1)) * tier.stat_multiplier) * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        magic: (dec!(1) + (magic - dec!(1)) * tier.stat_multiplier) * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        speed: speed * tier.stat_multiplier * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                        ability: ability,
+                        ability_odds: accessory_info.ability_active_odds * rng::seed_decimal(min_roll,max_roll,dec!(100)),       
+                    };
+                    self.system_vault.authorize(|| 
+                        borrow_resource_manager!(self.accessory_nft)
+                            .mint_non_fungible(&NonFungibleId::random(), accessory_data))
+                }
+                structs::ItemTemplate::Gem(id) => {
+                    let gem_info = self.game_data.gem_data.get(&id).unwrap();
+                    let gem_data = structs::Gem {
+                        id: id,
+                        stat: gem_info.stat,
+                        value: gem_info.value * rng::seed_decimal(min_roll,max_roll,dec!(100)),
+                    };
+                    self.system_vault.authorize(|| 
+                        borrow_resource_manager!(self.gem_nft)
+                            .mint_non_fungible(&NonFungibleId::random(), gem_data))
+                }
+            }
         }
-        // Creates weapons
-        pub fn create_weapon(&mut self, mut gold: Bucket, mut resource1: Bucket, mut resource2: Bucket, class: u64, id: Decimal) -> (Bucket,Bucket,Bucket,Bucket) {
-            let weapon_info = self.game_data.weapon_data.get(&(class,id)).unwrap();
-            // Assertations so no cheating
+        // Restores durability of a Weapon/Armor/Accessory NFT. Gold and Greavite are burned per point restored, scaled by item level and rarity
+        pub fn repair(&mut self, item: Proof, mut gold: Bucket, mut greavite: Bucket) -> (Bucket, Bucket) {
             assert!(gold.resource_address() == self.token_gold);
-            assert!(resource1.resource_address() == weapon_info.crafting_data.resource_1);
-            assert!(resource2.resource_address() == weapon_info.crafting_data.resource_2);
-            let gold_bucket: Bucket = gold.take(weapon_info.crafting_data.gold_cost);
-            let resource1_bucket: Bucket = resource1.take(weapon_info.crafting_data.resource_1_cost);
-            let resource2_bucket: Bucket = resource2.take(weapon_info.crafting_data.resource_2_cost);
-            let seed = rng::seed(weapon_info.min_rng_roll, weapon_info.max_rng_roll);
-            let ability = if seed == weapon_info.success_number || seed >= weapon_info.success_number {
-                weapon_info.ability_if_success
+            assert!(greavite.resource_address() == self.token_greavite);
+            let id = item.non_fungible_ids().into_iter().next().unwrap();
+            let mut info = self.get_item_info(item.resource_address(), &id);
+            let points: Decimal = (info.max_durability - info.durability).into();
+            let level: Decimal = info.level.into();
+            let cost = points * level * self.value_multiplier(info.rarity);
+            let gold_bucket: Bucket = gold.take(cost * self.game_data.durability_data.gold_per_point);
+            let greavite_bucket: Bucket = greavite.take(cost * self.game_data.durability_data.greavite_per_point);
+            info.durability = info.max_durability;
+            self.set_item_info(item.resource_address(), &id, info);
+
+            ComponentAuthZone::push(self.system_vault.create_proof());
+
+            gold_bucket.burn();
+            greavite_bucket.burn();
+
+            ComponentAuthZone::pop();
+
+            (gold, greavite)
+        }
+        // Burns Weapon/Armor/Accessory NFTs for Greavite, Wood and Gold, with a chance of getting a fusion catalyst for each item
+        pub fn salvage(&mut self, items: Bucket) -> (Bucket, Bucket, Bucket, Bucket) {
+            let resource = items.resource_address();
+            let salvage_info = self.game_data.salvage_data.get(&self.category_of(resource)).unwrap().clone();
+            let mut value = dec!(0);
+            let mut catalysts: u64 = 0;
+            for id in items.non_fungible_ids() {
+                let info = self.get_item_info(resource, &id);
+                // Gems and skins must be removed first, so they are not lost
+                assert!(info.sockets.is_empty());
+                assert!(info.skin.is_none());
+                let level: Decimal = info.level.into();
+                value += level * self.value_multiplier(info.rarity);
+                if rng::seed(0, 100) < salvage_info.catalyst_odds {
+                    catalysts += 1;
+                }
+            }
+
+            ComponentAuthZone::push(self.system_vault.create_proof());
+
+            items.burn();
+            let greavite = borrow_resource_manager!(self.token_greavite).mint(value * salvage_info.greavite);
+            let wood = borrow_resource_manager!(self.token_wood).mint(value * salvage_info.wood);
+            let gold = borrow_resource_manager!(self.token_gold).mint(value * salvage_info.gold);
+            let catalyst = borrow_resource_manager!(self.token_catalyst).mint(catalysts);
+
+            ComponentAuthZone::pop();
+
+            (greavite, wood, gold, catalyst)
+        }
+        // Gets the marketplace category of a Weapon/Armor/Accessory resource
+        fn category_of(&self, resource: ResourceAddress) -> structs::Categories {
+            if resource == self.weapon_nft {
+                structs::Categories::Weapon
+            }
+            else if resource == self.armor_nft {
+                structs::Categories::Armor
             }
             else {
-                dec!(0)
+                assert!(resource == self.accessory_nft);
+                structs::Categories::Accessory
+            }
+        }
+        // Burns a gem and places its data into a free socket of a Weapon/Armor/Accessory NFT
+        pub fn socket_gem(&mut self, item: Proof, gem: Bucket) {
+            assert!(gem.resource_address() == self.gem_nft);
+            let gem_data: structs::Gem = gem.non_fungible().data();
+            let id = item.non_fungible_ids().into_iter().next().unwrap();
+            let mut info = self.get_item_info(item.resource_address(), &id);
+            assert!((info.sockets.len() as u64) < self.socket_capacity(&info));
+            info.sockets.push(gem_data);
+            self.set_item_info(item.resource_address(), &id, info);
+            self.system_vault.authorize(|| 
+                gem.burn());
+        }
+        // Removes the gem in the given socket of a Weapon/Armor/Accessory NFT, and mints it back as a gem NFT
+        pub fn unsocket_gem(&mut self, item: Proof, socket: u64) -> Bucket {
+            let id = item.non_fungible_ids().into_iter().next().unwrap();
+            let mut info = self.get_item_info(item.resource_address(), &id);
+            assert!((socket as usize) < info.sockets.len());
+            let gem_data = info.sockets.remove(socket as usize);
+            self.set_item_info(item.resource_address(), &id, info);
+            self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.gem_nft)
+                    .mint_non_fungible(&NonFungibleId::random(), gem_data))
+        }
+        // Sockets of an item are set by its rarity, plus one every levels_per_socket levels
+        fn socket_capacity(&self, info: &structs::Item) -> u64 {
+            let rarity_slots = match self.game_data.rarity_data.iter().find(|x| x.rarity == info.rarity) {
+                Some(tier) => tier.socket_slots,
+                None => 0,
             };
-            let info = structs::Item {
-                id: id,
-                version: self.game_data.game_version,
-                weight: weapon_info.crafting_data.weight * rng::seed_decimal(75,125,dec!(100)),
-                level: 1, 
-            };  
-            let weapon_data = structs::Weapon {  
-                class: class,
-                item_info: info,
-                physical_base: weapon_info.physical_base * rng::seed_decimal(75,125,dec!(100)),
-                physical_scaling: weapon_info.physical_scaling * rng::seed_decimal(75,125,dec!(100)),
-                spell_base: weapon_info.magic_base * rng::seed_decimal(75,125,dec!(100)),
-                spell_scaling: weapon_info.magic_scaling * rng::seed_decimal(75,125,dec!(100)),
-                ability: ability,
-                ability_odds: weapon_info.ability_active_odds * rng::seed_decimal(75,125,dec!(100)),
-                range: weapon_info.range,
+            let level_slots = (info.level - 1) / self.game_data.socket_data.levels_per_socket;
+            std::cmp::min(rarity_slots + level_slots, self.game_data.socket_data.max_sockets)
+        }
+        // Links a skin to a Character/Weapon/Armor/Accessory NFT. The skin is held by the component until removed
+        pub fn apply_skin(&mut self, skin: Bucket, target: Proof) {
+            assert!(skin.resource_address() == self.skin_nft);
+            let skin_data: structs::Skin = skin.non_fungible().data();
+            let applied = structs::AppliedSkin { skin_id: skin.non_fungible::<structs::Skin>().id(), image_key: skin_data.image_key };
+            let resource = target.resource_address();
+            let id = target.non_fungible_ids().into_iter().next().unwrap();
+            if resource == self.character_nft {
+                assert!(skin_data.target == structs::SkinTarget::Character, "Skin can't be applied to a character");
+                let mut data: structs::Character = target.non_fungible().data();
+                assert!(data.skin.is_none(), "Character already has a skin");
+                data.skin = Some(applied);
+                self.system_vault.authorize(|| target.non_fungible().update_data(data));
+            }
+            else {
+                let skin_target = match self.category_of(resource) {
+                    structs::Categories::Weapon => structs::SkinTarget::Weapon,
+                    structs::Categories::Armor => structs::SkinTarget::Armor,
+                    _ => structs::SkinTarget::Accessory,
+                };
+                assert!(skin_data.target == skin_target, "Skin can't be applied to this gear");
+                let mut info = self.get_item_info(resource, &id);
+                assert!(info.skin.is_none(), "Gear already has a skin");
+                info.skin = Some(applied);
+                self.set_item_info(resource, &id, info);
+            }
+            self.skin_vault.put(skin);
+        }
+        // Unlinks the skin of a Character/Weapon/Armor/Accessory NFT and returns it, so it can be reapplied or sold
+        pub fn remove_skin(&mut self, target: Proof) -> Bucket {
+            let resource = target.resource_address();
+            let id = target.non_fungible_ids().into_iter().next().unwrap();
+            let applied = if resource == self.character_nft {
+                let mut data: structs::Character = target.non_fungible().data();
+                let applied = data.skin.take();
+                self.system_vault.authorize(|| target.non_fungible().update_data(data));
+                applied
+            }
+            else {
+                let mut info = self.get_item_info(resource, &id);
+                let applied = info.skin.take();
+                self.set_item_info(resource, &id, info);
+                applied
             };
+            let applied = applied.expect("No skin to remove");
+            self.skin_vault.take_non_fungible(&applied.skin_id)
+        }
+        // Mints the Gold and skin rewards of an unlocked achievement. Each achievement can only be claimed once per character
+        pub fn claim_achievement(&mut self, character: Proof, achievement_id: u64) -> (Bucket, Bucket) {
+            assert!(character.resource_address() == self.character_nft);
+            let mut data: structs::Character = character.non_fungible().data();
+            assert!(data.achievements.contains(&achievement_id), "Achievement not unlocked");
+            assert!(!data.claimed_achievements.contains(&achievement_id), "Achievement already claimed");
+            let achievement = self.game_data.achievement_data.get(&achievement_id).unwrap().clone();
+            data.claimed_achievements.push(achievement_id);
+
             ComponentAuthZone::push(self.system_vault.create_proof());
 
-            let new_weapon = borrow_resource_manager!(self.weapon_nft)
-                .mint_non_fungible(&NonFungibleId::random(), weapon_data);
-            gold_bucket.burn();
-            resource1_bucket.burn();
-            resource2_bucket.burn();
+            let gold = borrow_resource_manager!(self.token_gold).mint(achievement.gold_reward);
+            let skin = match achievement.skin_reward {
+                Some(skin_data) => borrow_resource_manager!(self.skin_nft).mint_non_fungible(&NonFungibleId::random(), skin_data),
+                None => Bucket::new(self.skin_nft),
+            };
+            character.non_fungible().update_data(data);
 
             ComponentAuthZone::pop();
 
-            (new_weapon,gold,resource1,resource2)
+            (gold, skin)
         }
-        pub fn create_armor(&mut self, mut gold: Bucket, mut resource1: Bucket, mut resource2: Bucket, armor: structs::ArmorNames, id: Decimal) -> (Bucket,Bucket,Bucket,Bucket) {
-            let armor_info = self.game_data.armor_data.get(&(armor,id)).unwrap();
-            let gold_bucket: Bucket = gold.take(armor_info.crafting_data.gold_cost);
-            let resource1_bucket: Bucket = resource1.take(armor_info.crafting_data.resource_1_cost);
-            let resource2_bucket: Bucket = resource2.take(armor_info.crafting_data.resource_2_cost);
-            assert!(gold.resource_address() == self.token_gold);
-            assert!(resource1.resource_address() == armor_info.crafting_data.resource_1);
-            assert!(resource2.resource_address() == armor_info.crafting_data.resource_2);
-            let chance = rng::seed(1, 2);
-            let health = if chance == 1 { armor_info.health_bonus }
-            else { armor_info.health };
-            let defense = if chance == 2 { armor_info.defense_bonus }
-            else { armor_info.defense };
-            let info = structs::Item {
-                id: id,
-                version: self.game_data.game_version,
-                weight: armor_info.crafting_data.weight * rng::seed_decimal(75,125,dec!(100)),
-                level: 1, 
-            };  
-            let weapon_data = structs::Armor {  
-                item_info: info,
-                part: armor,
-                health: health * rng::seed_decimal(75,125,dec!(100)),
-                defense: defense * rng::seed_decimal(75,125,dec!(100)),
-            };
+        // Mints the rewards of a finished quest, and adds its EXP to the character. Each quest can be claimed once per reset window
+        pub fn claim_quest(&mut self, character: Proof, quest_id: u64) -> (Bucket, Bucket, Bucket) {
+            assert!(character.resource_address() == self.character_nft);
+            let mut data: structs::Character = character.non_fungible().data();
+            let quest = self.game_data.quest_data.get(&quest_id).unwrap().clone();
+            let window = Runtime::current_epoch() / quest.reset_epochs;
+            let progress = data.quests.get_mut(&quest_id).expect("Quest not started");
+            assert!(progress.window == window, "Quest progress has been reset");
+            assert!(progress.progress >= quest.amount, "Quest not finished");
+            assert!(!progress.claimed, "Quest already claimed");
+            progress.claimed = true;
+            data.exp += quest.exp_reward;
+            data = self.levelup(data);
+            self.unlock_achievements(&mut data);
+
             ComponentAuthZone::push(self.system_vault.create_proof());
 
-            let new_weapon = borrow_resource_manager!(self.weapon_nft)
-                .mint_non_fungible(&NonFungibleId::random(), weapon_data);
-            gold_bucket.burn();
-            resource1_bucket.burn();
-            resource2_bucket.burn();
+            let gold = borrow_resource_manager!(self.token_gold).mint(quest.gold_reward);
+            let greavite = borrow_resource_manager!(self.token_greavite).mint(quest.greavite_reward);
+            let wood = borrow_resource_manager!(self.token_wood).mint(quest.wood_reward);
+            character.non_fungible().update_data(data);
 
             ComponentAuthZone::pop();
 
-            (new_weapon,gold,resource1,resource2)
+            (gold, greavite, wood)
         }
-        pub fn create_accessory(&mut self, mut gold: Bucket, mut resource1: Bucket, mut resource2: Bucket, accessory: structs::AccessoryNames, id: Decimal) -> (Bucket,Bucket,Bucket,Bucket) {
-            let accessory_info = self.game_data.accessory_data.get(&(accessory,id)).unwrap();
-            let gold_bucket: Bucket = gold.take(accessory_info.crafting_data.gold_cost);
-            let resource1_bucket: Bucket = resource1.take(accessory_info.crafting_data.resource_1_cost);
-            let resource2_bucket: Bucket = resource2.take(accessory_info.crafting_data.resource_2_cost);
-            assert!(gold.resource_address() == self.token_gold);
-            assert!(resource1.resource_address() == accessory_info.crafting_data.resource_1);
-            assert!(resource2.resource_address() == accessory_info.crafting_data.resource_2);
-            let seed = rng::seed(accessory_info.min_rng_roll, accessory_info.max_rng_roll);
-            let ability = if seed == accessory_info.success_number || seed >= accessory_info.success_number {
-                accessory_info.ability_if_success
+        // Adds to the achievement counter and quest progress of a character. Achievements are unlocked separately
+        fn record(&self, data: &mut structs::Character, task: structs::Task, amount: u64) {
+            *data.counters.entry(task.counter()).or_insert(0) += amount;
+            for (id, quest) in self.game_data.quest_data.iter() {
+                if quest.task != task {
+                    continue;
+                }
+                // Progress from an earlier window is thrown away
+                let window = Runtime::current_epoch() / quest.reset_epochs;
+                let progress = data.quests.entry(*id).or_insert(structs::QuestProgress { window, progress: 0, claimed: false });
+                if progress.window != window {
+                    *progress = structs::QuestProgress { window, progress: 0, claimed: false };
+                }
+                progress.progress += amount;
+            }
+        }
+        // Unlocks every achievement whose goal the character has met, and adds its title
+        fn unlock_achievements(&self, data: &mut structs::Character) {
+            for (id, achievement) in self.game_data.achievement_data.iter() {
+                if data.achievements.contains(id) {
+                    continue;
+                }
+                let met = match &achievement.goal {
+                    structs::Goal::Count(counter, amount) => *data.counters.get(counter).unwrap_or(&0) >= *amount,
+                    structs::Goal::Level(level) => data.level >= *level,
+                };
+                if met {
+                    data.achievements.push(*id);
+                    data.titles.push(achievement.title.clone());
+                }
+            }
+        }
+        // Records an action for the character of a Proof, and saves any newly unlocked achievements
+        fn record_for(&self, character: &Proof, task: structs::Task, amount: u64) {
+            assert!(character.resource_address() == self.character_nft);
+            let mut data: structs::Character = character.non_fungible().data();
+            self.record(&mut data, task, amount);
+            self.unlock_achievements(&mut data);
+            self.system_vault.authorize(|| character.non_fungible().update_data(data));
+        }
+        // Records an action for a character by id, for actions where the character's owner may not be the caller
+        fn record_for_id(&self, id: &NonFungibleId, task: structs::Task, amount: u64) {
+            let mut data: structs::Character = borrow_resource_manager!(self.character_nft).get_non_fungible_data(id);
+            self.record(&mut data, task, amount);
+            self.unlock_achievements(&mut data);
+            self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.character_nft)
+                    .update_non_fungible_data(id, data));
+        }
+        // Reads the shared item data of any Weapon/Armor/Accessory NFT
+        fn get_item_info(&self, resource: ResourceAddress, id: &NonFungibleId) -> structs::Item {
+            if resource == self.weapon_nft {
+                borrow_resource_manager!(resource).get_non_fungible_data::<structs::Weapon>(id).item_info
+            }
+            else if resource == self.armor_nft {
+                borrow_resource_manager!(resource).get_non_fungible_data::<structs::Armor>(id).item_info
             }
             else {
-                dec!(0)
-            };
-            let chance = rng::seed(1, 3);
-            let attack = if chance == 1 { accessory_info.attack_bonus }
-            else { accessory_info.attack };
-            let magic = if chance == 2 { accessory_info.magic_bonus }
-            else { accessory_info.magic };
-            let speed = if chance == 3 { accessory_info.speed_bonus }
-            else { accessory_info.speed };
-            let info = structs::Item {
-                id: id,
-                version: self.game_data.game_version,
-                weight: accessory_info.crafting_data.weight * rng::seed_decimal(75,125,dec!(100)),
-                level: 1, 
-            };  
-            let weapon_data = structs::Accessory {  
-                item_info: info,
-                part: accessory,
-                attack: attack * rng::seed_decimal(75,125,dec!(100)),
-                magic: magic * rng::seed_decimal(75,125,dec!(100)),
-                speed: speed * rng::seed_decimal(75,125,dec!(100)),
-                ability: ability,
-                ability_odds: accessory_info.ability_active_odds * rng::seed_decimal(75,125,dec!(100)),       
-            };
+                assert!(resource == self.accessory_nft);
+                borrow_resource_manager!(resource).get_non_fungible_data::<structs::Accessory>(id).item_info
+            }
+        }
+        // Overwrites the shared item data of any Weapon/Armor/Accessory NFT
+        fn set_item_info(&self, resource: ResourceAddress, id: &NonFungibleId, info: structs::Item) {
             ComponentAuthZone::push(self.system_vault.create_proof());
 
-            let new_weapon = borrow_resource_manager!(self.weapon_nft)
-                .mint_non_fungible(&NonFungibleId::random(), weapon_data);
-            gold_bucket.burn();
-            resource1_bucket.burn();
-            resource2_bucket.burn();
+            if resource == self.weapon_nft {
+                let mut data: structs::Weapon = borrow_resource_manager!(resource).get_non_fungible_data(id);
+                data.item_info = info;
+                borrow_resource_manager!(resource).update_non_fungible_data(id, data);
+            }
+            else if resource == self.armor_nft {
+                let mut data: structs::Armor = borrow_resource_manager!(resource).get_non_fungible_data(id);
+                data.item_info = info;
+                borrow_resource_manager!(resource).update_non_fungible_data(id, data);
+            }
+            else {
+                assert!(resource == self.accessory_nft);
+                let mut data: structs::Accessory = borrow_resource_manager!(resource).get_non_fungible_data(id);
+                data.item_info = info;
+                borrow_resource_manager!(resource).update_non_fungible_data(id, data);
+            }
 
             ComponentAuthZone::pop();
-
-            (new_weapon,gold,resource1,resource2)
         }
-        // List gear on the marketplace. Prices are set in gold
-        pub fn list_single_gear(&mut self, mut listing: Bucket, price: Decimal, category: structs::Categories) -> Bucket {
-            let id = match category {
-                structs::Categories::Weapon => listing.non_fungible::<structs::Weapon>().id(),
-                structs::Categories::Armor => listing.non_fungible::<structs::Armor>().id(),
-                structs::Categories::Accessory => listing.non_fungible::<structs::Accessory>().id(),
+        // Value of a rarity tier, used to scale repair costs and salvage returns. 1 if the tier has no data
+        fn value_multiplier(&self, rarity: structs::Rarity) -> Decimal {
+            match self.game_data.rarity_data.iter().find(|x| x.rarity == rarity) {
+                Some(tier) => tier.value_multiplier,
+                None => dec!(1),
+            }
+        }
+        // Rolls an affix for every slot, alternating between prefixes and suffixes. Tier of the roll is the item level
+        fn roll_affixes(&self, slots: u64, level: u64) -> Vec<structs::Affix> {
+            let mut affixes: Vec<structs::Affix> = Vec::new();
+            for slot in 0..slots {
+                let kind = if slot % 2 == 0 { structs::AffixKind::Prefix } else { structs::AffixKind::Suffix };
+                let mut pool: Vec<(&u64, &structs::AffixData)> = self.game_data.affix_pool.iter()
+                    .filter(|x| x.1.kind == kind).collect();
+                if pool.is_empty() {
+                    continue;
+                }
+                pool.sort_by_key(|x| *x.0);
+                let (affix_id, data) = pool[rng::seed(0, pool.len() as u128) as usize];
+                let tier = std::cmp::min(std::cmp::max(level, 1) as usize, data.tiers.len()) - 1;
+                let (min, max) = data.tiers[tier];
+                affixes.push(structs::Affix {
+                    affix_id: *affix_id,
+                    kind: kind,
+                    modifier: data.modifier,
+                    value: rng::seed(min, max + 1),
+                });
+            }
+            affixes
+        }
+        // Rolls a rarity tier from the weight table. Items are common if no table is uploaded
+        fn roll_rarity(&self) -> structs::RarityData {
+            if self.game_data.rarity_data.is_empty() {
+                return structs::RarityData { rarity: structs::Rarity::Common, weight: 1, stat_multiplier: dec!(1), affix_slots: 0, value_multiplier: dec!(1), socket_slots: 0 }
+            }
+            let weights: Vec<u128> = self.game_data.rarity_data.iter().map(|x| x.weight).collect();
+            self.game_data.rarity_data[rng::weighted_index(weights)].clone()
+        }
+        // List gear on the marketplace. Prices are set in any accepted token, and the listing can't be bought from the expiry epoch on
+        pub fn list_single_gear(&mut self, character: Proof, listing: Bucket, price: Decimal, payment: ResourceAddress, category: structs::Categories, expiry_epoch: Option<u64>) -> Bucket {
+            self.assert_single(&listing, category);
+            self.list(&character, vec![listing], price, payment, structs::ListingKind::Fixed, expiry_epoch)
+        }
+        // List several items as one bundle, like a full armor set. The bundle has a single price, and is only sold as a whole
+        pub fn list_bundle(&mut self, character: Proof, bundle: Vec<Bucket>, price: Decimal, payment: ResourceAddress, expiry_epoch: Option<u64>) -> Bucket {
+            self.list(&character, bundle, price, payment, structs::ListingKind::Fixed, expiry_epoch)
+        }
+        // List gear as an English auction. Bids are in the payment token, and each bid must beat the highest by the minimum increment
+        pub fn list_auction(&mut self, character: Proof, listing: Bucket, category: structs::Categories, payment: ResourceAddress, start_bid: Decimal, reserve_price: Decimal, min_increment: Decimal, end_epoch: u64) -> Bucket {
+            self.assert_single(&listing, category);
+            assert!(end_epoch > Runtime::current_epoch(), "Auction must end in the future");
+            assert!(min_increment > dec!(0), "Minimum increment must be positive");
+            let auction = structs::Auction {
+                start_bid: start_bid,
+                reserve_price: reserve_price,
+                min_increment: min_increment,
+                end_epoch: end_epoch,
+                highest_bid: None,
+            };
+            self.list(&character, vec![listing], start_bid, payment, structs::ListingKind::English(auction), None)
+        }
+        // List gear as a Dutch auction. The price falls every epoch, and the first buyer pays the price at the time of purchase
+        pub fn list_dutch_auction(&mut self, character: Proof, listing: Bucket, category: structs::Categories, payment: ResourceAddress, start_price: Decimal, floor_price: Decimal, duration: u64, decay: structs::Decay, expiry_epoch: Option<u64>) -> Bucket {
+            self.assert_single(&listing, category);
+            assert!(floor_price >= dec!(0), "Floor price can't be negative");
+            assert!(start_price >= floor_price, "Start price can't be below the floor price");
+            assert!(duration > 0, "Duration must be at least 1 epoch");
+            if let structs::Decay::Exponential(percent) = decay {
+                assert!(percent > 0 && percent < 100, "Exponential decay must be between 0 and 100%");
+            }
+            let auction = structs::DutchAuction {
+                start_price: start_price,
+                floor_price: floor_price,
+                start_epoch: Runtime::current_epoch(),
+                duration: duration,
+                decay: decay,
             };
-            let new_id = NonFungibleId::random();
-            let gear = listing.take(1);
+            self.list(&character, vec![listing], start_price, payment, structs::ListingKind::Dutch(auction), expiry_epoch)
+        }
+        // Checks a single item listing holds one NFT of the given category
+        fn assert_single(&self, listing: &Bucket, category: structs::Categories) {
+            assert!(listing.amount() == dec!(1), "Only a single item can be listed, use a bundle for more");
+            assert!(self.market_category(listing.resource_address()) == category, "Item does not match the category");
+        }
+        // Stores listed gear and mints the seller's receipt, which keys the listing. For auctions the receipt price is set to the realised price on sale
+        fn list(&mut self, character: &Proof, listings: Vec<Bucket>, price: Decimal, payment: ResourceAddress, kind: structs::ListingKind, expiry_epoch: Option<u64>) -> Bucket {
+            assert!(character.resource_address() == self.character_nft);
+            assert!(!listings.is_empty(), "Nothing to list");
+            assert!(self.game_data.payment_tokens.contains(&payment), "Payment token is not accepted");
+            if let Some(expiry) = expiry_epoch {
+                assert!(expiry > Runtime::current_epoch(), "Listing must expire in the future");
+            }
+            let seller = character.non_fungible_ids().into_iter().next().unwrap();
+            let mut items: Vec<structs::ListedItem> = Vec::new();
+            for listing in listings {
+                let resource = listing.resource_address();
+                let category = self.market_category(resource);
+                for id in listing.non_fungible_ids() {
+                    let rarity = if category == structs::Categories::Skin { structs::Rarity::Common } 
+                        else { self.get_item_info(resource, &id).rarity };
+                    items.push(structs::ListedItem { category: category, id: id, rarity: rarity });
+                }
+                self.marketplace_vault(category).put(listing);
+            }
+            // Catches bundles of empty buckets
+            assert!(!items.is_empty(), "Nothing to list");
             let receipt_data = structs::Receipt {
                 price: price,
-                item_id: id,
-                category: category,
-                id: new_id,
+                payment: payment,
+                id: NonFungibleId::random(),
+                items: items,
+                state: structs::ListingState::Active,
+                payout: dec!(0),
+                seller: seller.clone(),
             };
-            let new_receipt = borrow_resource_manager!(self.receipt_nft)
-                .mint_non_fungible(&receipt_data.id, receipt_data.clone());
-            self.marketplace_listings.insert((category, receipt_data.item_id.clone()), (receipt_data.clone(), false));
-            match category {
-                structs::Categories::Weapon => self.marketplace_weapon_vault.put(gear),
-                structs::Categories::Armor => self.marketplace_armor_vault.put(gear),
-                structs::Categories::Accessory => self.marketplace_accessory_vault.put(gear),
+            let new_receipt = self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.receipt_nft)
+                    .mint_non_fungible(&receipt_data.id, receipt_data.clone()));
+            let listing_data = structs::Listing {
+                receipt: receipt_data.clone(),
+                kind: kind,
+                seller: seller,
+                expiry_epoch: expiry_epoch,
             };
+            self.marketplace_listings.insert(receipt_data.id.clone(), listing_data);
+            self.listing_index.push(receipt_data.id.clone());
             new_receipt
         }
-        // Buy gear from the marketplace. Prices are set in gold
-        pub fn buy_single_gear(&mut self, mut gold: Bucket, category: structs::Categories, id: NonFungibleId,) -> (Bucket, Bucket) {
-            let nft = match category {
-                structs::Categories::Armor => self.marketplace_armor_vault.take_non_fungible(&id),
-                structs::Categories::Accessory => self.marketplace_accessory_vault.take_non_fungible(&id),
-                structs::Categories::Weapon => self.marketplace_weapon_vault.take_non_fungible(&id),
-            };
-            let mut lazymap_data = self.marketplace_listings.get(&(category, id)).unwrap();
-            assert!(lazymap_data.1 == false);
-            lazymap_data.1 = true;
-            let payment = gold.take(lazymap_data.0.price * dec!(".95"));
-            let burn_bucket: Bucket = gold.take(lazymap_data.0.price * dec!(".05"));
-            self.gold_vault.put(payment);
-            self.marketplace_listings.insert((category, lazymap_data.0.id.clone()), lazymap_data);
+        // Buy a listing from the marketplace with its payment token. Dutch auctions sell at their current price.
+        // Bundles return all their items, one bucket per resource
+        pub fn buy_single_gear(&mut self, character: Proof, mut payment: Bucket, listing_id: NonFungibleId) -> (Bucket, Vec<Bucket>) {
+            let mut listing = self.marketplace_listings.get(&listing_id).unwrap().clone();
+            assert!(payment.resource_address() == listing.receipt.payment, "Listing is priced in another token");
+            assert!(!matches!(listing.kind, structs::ListingKind::English(_)), "Auctions can only be bid on");
+            assert!(!listing.is_expired(Runtime::current_epoch()), "Listing has expired");
+            // Receipt records the realised price, so the seller redeems what was paid
+            listing.receipt.price = listing.current_price(Runtime::current_epoch());
+            let nfts = self.take_items(&listing.receipt.items);
+            let payout = self.take_fees(payment.take(listing.receipt.price), &listing.receipt.items);
+            listing.receipt.payout = payout.amount();
+            Self::token_vault(&mut self.proceeds_vaults, listing.receipt.payment).put(payout);
+            self.record_sale(&listing.receipt.items, listing.receipt.price, listing.receipt.payment);
+            self.update_listing(listing, structs::ListingState::Sold);
+            self.record_for(&character, structs::Task::Buy, 1);
+            (payment,nfts)
+        }
+        // Bid on an auction. The bid is escrowed until outbid or the auction is settled, and can be redeemed with the returned bid NFT
+        pub fn bid(&mut self, mut payment: Bucket, listing_id: NonFungibleId, amount: Decimal) -> (Bucket, Bucket) {
+            let mut listing = self.marketplace_listings.get(&listing_id).unwrap().clone();
+            let token = listing.receipt.payment;
+            assert!(payment.resource_address() == token, "Auction is priced in another token");
+            let epoch = Runtime::current_epoch();
+            assert!(listing.receipt.state == structs::ListingState::Active, "Auction has ended");
+            let items = listing.receipt.items.clone();
+            let auction = match &mut listing.kind {
+                structs::ListingKind::English(auction) => auction,
+                _ => panic!("Listing is not an auction"),
+            };
+            assert!(epoch < auction.end_epoch, "Auction has ended");
+            let min_bid = match &auction.highest_bid {
+                Some((_, highest)) => *highest + auction.min_increment,
+                None => auction.start_bid,
+            };
+            assert!(amount >= min_bid, "Bid must be at least {}", min_bid);
+            Self::token_vault(&mut self.bid_vaults, token).put(payment.take(amount));
+            let bid_id = NonFungibleId::random();
+            let bid_data = structs::Bid {
+                listing_id: listing_id.clone(),
+                items: items,
+                amount: amount,
+                payment: token,
+                won: false,
+            };
+            let new_bid = self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.bid_nft)
+                    .mint_non_fungible(&bid_id, bid_data));
+            auction.highest_bid = Some((bid_id, amount));
+            // Late bids extend the auction, so outbid bidders have time to respond
+            if auction.end_epoch - epoch < self.game_data.auction_extension {
+                auction.end_epoch = epoch + self.game_data.auction_extension;
+            }
+            self.marketplace_listings.insert(listing_id, listing);
+            (payment, new_bid)
+        }
+        // Ends an auction once its end epoch is reached. If the reserve price was met, the winning bid pays out like a sale.
+        // Otherwise the auction expires, and the seller can take the item back
+        pub fn settle_auction(&mut self, listing_id: NonFungibleId) {
+            let mut listing = self.marketplace_listings.get(&listing_id).unwrap().clone();
+            let auction = match &listing.kind {
+                structs::ListingKind::English(auction) => auction.clone(),
+                _ => panic!("Listing is not an auction"),
+            };
+            assert!(listing.receipt.state == structs::ListingState::Active, "Auction is already settled");
+            assert!(Runtime::current_epoch() >= auction.end_epoch, "Auction has not ended");
+            match auction.highest_bid {
+                Some((bid_id, amount)) if amount >= auction.reserve_price => {
+                    listing.receipt.price = amount;
+                    let token = listing.receipt.payment;
+                    let winning_bid = Self::token_vault(&mut self.bid_vaults, token).take(amount);
+                    let payout = self.take_fees(winning_bid, &listing.receipt.items);
+                    listing.receipt.payout = payout.amount();
+                    Self::token_vault(&mut self.proceeds_vaults, token).put(payout);
+                    let mut bid_data: structs::Bid = borrow_resource_manager!(self.bid_nft).get_non_fungible_data(&bid_id);
+                    bid_data.won = true;
+                    self.system_vault.authorize(|| 
+                        borrow_resource_manager!(self.bid_nft)
+                            .update_non_fungible_data(&bid_id, bid_data));
+                    self.record_sale(&listing.receipt.items, amount, token);
+                    self.update_listing(listing, structs::ListingState::Sold);
+                }
+                _ => self.update_listing(listing, structs::ListingState::Expired),
+            }
+        }
+        // Losing bids are refunded, and the winning bid is redeemed for the items.
+        // The highest bid of an auction which has not been settled yet can't be redeemed
+        pub fn redeem_bid(&mut self, character: Proof, bid: Bucket) -> Vec<Bucket> {
+            assert!(bid.resource_address() == self.bid_nft);
+            let bid_nft = bid.non_fungible::<structs::Bid>();
+            let bid_data = bid_nft.data();
+            let redeemed = if bid_data.won {
+                self.record_for(&character, structs::Task::Buy, 1);
+                self.take_items(&bid_data.items)
+            }
+            else {
+                if let Some(listing) = self.marketplace_listings.get(&bid_data.listing_id) {
+                    if let structs::ListingKind::English(auction) = &listing.kind {
+                        let highest = auction.highest_bid.as_ref().map_or(false, |x| x.0 == bid_nft.id());
+                        assert!(!(highest && listing.receipt.state == structs::ListingState::Active), "The highest bid is locked until the auction is settled");
+                    }
+                }
+                vec![Self::token_vault(&mut self.bid_vaults, bid_data.payment).take(bid_data.amount)]
+            };
             self.system_vault.authorize(|| 
-                burn_bucket.burn());
-            (gold,nft)
+                bid.burn());
+            redeemed
         }
-        // Get your gold from a sold item
-        pub fn redeem_receipt(&mut self, receipt: Bucket) -> Bucket {
-            let receipt_data: structs::Receipt = receipt.non_fungible().data();
-            let lazymap_data = self.marketplace_listings.get(&(receipt_data.category, receipt_data.item_id.clone())).unwrap();
+        // Escrows any accepted token for an offer on a specific NFT, or on any item matching the criteria.
+        // The returned offer NFT redeems the item once the offer is accepted, or cancels the offer and refunds the tokens
+        pub fn make_offer(&mut self, mut payment: Bucket, target: structs::OfferTarget, price: Decimal) -> (Bucket, Bucket) {
+            let token = payment.resource_address();
+            assert!(self.game_data.payment_tokens.contains(&token), "Payment token is not accepted");
+            assert!(price > dec!(0), "Offer price must be positive");
+            if let structs::OfferTarget::Criteria(category, _, _) = target {
+                assert!(category != structs::Categories::Skin, "Skins can only get offers by id");
+            }
+            Self::token_vault(&mut self.offer_vaults, token).put(payment.take(price));
+            let offer_data = structs::Offer {
+                target: target,
+                price: price,
+                payment: token,
+                filled: None,
+                seller: None,
+            };
+            let offer = self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.offer_nft)
+                    .mint_non_fungible(&NonFungibleId::random(), offer_data));
+            (payment, offer)
+        }
+        // Sells an item to an offer it matches. The item is held for the offer holder to redeem
+        // Character NFTs can't be withdrawn, so the character proof belongs to whoever hands over the item
+        pub fn accept_offer(&mut self, character: Proof, offer_id: NonFungibleId, item: Bucket) -> Bucket {
+            assert!(character.resource_address() == self.character_nft);
+            assert!(character.amount() == dec!(1), "Sell with a single character");
+            let seller = character.non_fungible_ids().into_iter().next().unwrap();
+            assert!(item.amount() == dec!(1));
+            // Fails for redeemed offers, as their NFT is burned
+            let mut offer: structs::Offer = borrow_resource_manager!(self.offer_nft).get_non_fungible_data(&offer_id);
+            assert!(offer.filled.is_none(), "Offer was already accepted");
+            let resource = item.resource_address();
+            let category = self.market_category(resource);
+            let id = item.non_fungible_ids().into_iter().next().unwrap();
+            let matches = match &offer.target {
+                structs::OfferTarget::Item(target_category, target_id) => *target_category == category && *target_id == id,
+                structs::OfferTarget::Criteria(target_category, item_id, min_level) => *target_category == category && {
+                    let info = self.get_item_info(resource, &id);
+                    info.id == *item_id && info.level >= *min_level
+                },
+            };
+            assert!(matches, "Item does not match the offer");
+            let rarity = if category == structs::Categories::Skin { structs::Rarity::Common } 
+                else { self.get_item_info(resource, &id).rarity };
+            let sold = vec![structs::ListedItem { category: category, id: id.clone(), rarity: rarity }];
+            self.record_sale(&sold, offer.price, offer.payment);
+            let offer_tokens = Self::token_vault(&mut self.offer_vaults, offer.payment).take(offer.price);
+            let payment = self.take_fees(offer_tokens, &sold);
+            offer.filled = Some(id);
+            offer.seller = Some(seller.clone());
+            self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.offer_nft)
+                    .update_non_fungible_data(&offer_id, offer));
+            self.marketplace_vault(category).put(item);
+            self.record_for_id(&seller, structs::Task::Sell, 1);
+            payment
+        }
+        // Returns the bought item of an accepted offer, or cancels an open offer and refunds its tokens
+        pub fn redeem_offer(&mut self, character: Proof, offer: Bucket) -> Bucket {
+            assert!(offer.resource_address() == self.offer_nft);
+            let offer_data: structs::Offer = offer.non_fungible().data();
+            let redeemed = match offer_data.filled {
+                Some(id) => {
+                    self.record_for(&character, structs::Task::Buy, 1);
+                    let category = match offer_data.target {
+                        structs::OfferTarget::Item(category, _) => category,
+                        structs::OfferTarget::Criteria(category, _, _) => category,
+                    };
+                    self.marketplace_vault(category).take_non_fungible(&id)
+                }
+                None => Self::token_vault(&mut self.offer_vaults, offer_data.payment).take(offer_data.price),
+            };
+            self.system_vault.authorize(|| 
+                offer.burn());
+            redeemed
+        }
+        // Get the proceeds of a sold listing, in the token it was priced in
+        // The receipt is kept in the Redeemed state, so it can't be redeemed twice. The sale is credited to the character which listed it
+        pub fn redeem_receipt(&mut self, receipt: Proof) -> Bucket {
             // No cheating!
             assert!(receipt.resource_address() == self.receipt_nft);
-            assert!(lazymap_data.0 == receipt_data);
-            assert!(lazymap_data.1 == true);
-            // Can't remove a key pair from a LazyMap :( not exploitable but annoying af
-            // self.marketplace_listings.remove(receipt_data.category, receipt_data.item_id);
-            let gold = self.gold_vault.take(receipt_data.price * dec!(".95"));
-            self.system_vault.authorize(|| 
-                receipt.burn());
-            gold
+            let mut receipt_data: structs::Receipt = receipt.non_fungible().data();
+            assert!(receipt_data.state.can_become(structs::ListingState::Redeemed), "Listing has not been sold");
+            let proceeds = Self::token_vault(&mut self.proceeds_vaults, receipt_data.payment).take(receipt_data.payout);
+            receipt_data.state = structs::ListingState::Redeemed;
+            let seller = receipt_data.seller.clone();
+            self.system_vault.authorize(|| receipt.non_fungible().update_data(receipt_data));
+            self.record_for_id(&seller, structs::Task::Sell, 1);
+            proceeds
         }
-        // Get your item back from listing
-        pub fn remove_listing(&mut self, receipt: Bucket) -> Bucket {
-            let receipt_data: structs::Receipt = receipt.non_fungible().data();
-            let lazymap_data = self.marketplace_listings.get(&(receipt_data.category, receipt_data.item_id.clone())).unwrap();
+        // Get your items back from listing, whether it is active or expired. Running auctions can only be removed before the first bid
+        pub fn remove_listing(&mut self, receipt: Bucket) -> Vec<Bucket> {
             // No cheating!
             assert!(receipt.resource_address() == self.receipt_nft);
-            assert!(lazymap_data.0 == receipt_data);
-            assert!(lazymap_data.1 == false);
-            // Can't remove a key pair from a LazyMap :( not exploitable but annoying af
-            //self.marketplace_listings.remove(receipt_data.category, receipt_data.item_id);
-            let nft = match lazymap_data.0.category {
-                structs::Categories::Armor => self.marketplace_armor_vault.take_non_fungible(&lazymap_data.0.item_id),
-                structs::Categories::Accessory => self.marketplace_accessory_vault.take_non_fungible(&lazymap_data.0.item_id),
-                structs::Categories::Weapon => self.marketplace_weapon_vault.take_non_fungible(&lazymap_data.0.item_id),
-            };
+            let receipt_data: structs::Receipt = receipt.non_fungible().data();
+            let listing = self.marketplace_listings.get(&receipt_data.id).unwrap().clone();
+            if let structs::ListingKind::English(auction) = &listing.kind {
+                assert!(auction.highest_bid.is_none() || listing.receipt.state == structs::ListingState::Expired, "Auction has bids");
+            }
+            self.update_listing(listing, structs::ListingState::Cancelled);
+            let nfts = self.take_items(&receipt_data.items);
             self.system_vault.authorize(|| 
                 receipt.burn());
-            nft
+            nfts
         }
         pub fn change_listing_price(&mut self, receipt: Proof, new_price: Decimal) -> Decimal {
-            let mut receipt_data: structs::Receipt = receipt.non_fungible().data();
-            let lazymap_data = self.marketplace_listings.get(&(receipt_data.category, receipt_data.item_id.clone())).unwrap();
             // No cheating!
             assert!(receipt.resource_address() == self.receipt_nft);
-            assert!(lazymap_data.0 == receipt_data);
-            assert!(lazymap_data.1 == false);
+            let mut receipt_data: structs::Receipt = receipt.non_fungible().data();
+            let mut listing = self.marketplace_listings.get(&receipt_data.id).unwrap().clone();
+            assert!(listing.receipt.state == structs::ListingState::Active);
+            assert!(!listing.is_expired(Runtime::current_epoch()), "Listing has expired");
+            assert!(matches!(listing.kind, structs::ListingKind::Fixed), "Auction prices are set by bids");
             receipt_data.price = new_price;
-            self.marketplace_listings.insert((receipt_data.category, receipt_data.item_id.clone()), (receipt_data.clone(), false));
+            listing.receipt = receipt_data.clone();
+            self.marketplace_listings.insert(receipt_data.id.clone(), listing);
+            self.system_vault.authorize(|| receipt.non_fungible().update_data(receipt_data.clone()));
             receipt_data.price
         }
+        // Pages through active listings matching the filter, in the order they were listed
+        pub fn get_listings(&self, filter: structs::ListingFilter, start: u64, limit: u64) -> Vec<structs::Listing> {
+            let epoch = Runtime::current_epoch();
+            self.listing_index.iter()
+                .map(|id| self.marketplace_listings.get(id).unwrap().clone())
+                // Expired listings and ended auctions stay indexed until swept or settled
+                .filter(|listing| !listing.is_expired(epoch))
+                .filter(|listing| match &listing.kind {
+                    structs::ListingKind::English(auction) => epoch < auction.end_epoch,
+                    _ => true,
+                })
+                .filter(|listing| {
+                    let price = listing.current_price(epoch);
+                    let items = &listing.receipt.items;
+                    filter.category.map_or(true, |x| items.iter().any(|item| item.category == x))
+                        && filter.payment.map_or(true, |x| x == listing.receipt.payment)
+                        && filter.min_price.map_or(true, |x| price >= x)
+                        && filter.max_price.map_or(true, |x| price <= x)
+                        && filter.seller.as_ref().map_or(true, |x| *x == listing.seller)
+                        && (filter.min_level.is_none() && filter.max_level.is_none() || items.iter().any(|item| item.category != structs::Categories::Skin && {
+                            let level = self.get_item_info(self.resource_of(item.category), &item.id).level;
+                            filter.min_level.map_or(true, |x| level >= x) && filter.max_level.map_or(true, |x| level <= x)
+                        }))
+                })
+                .skip(start as usize)
+                .take(limit as usize)
+                .collect()
+        }
+        // Listing and full data of every listed NFT, in the order of the receipt
+        pub fn get_listing(&self, listing_id: NonFungibleId) -> (structs::Listing, Vec<structs::ItemData>) {
+            let listing = self.marketplace_listings.get(&listing_id).unwrap().clone();
+            let items = listing.receipt.items.iter()
+                .map(|item| {
+                    let resource_manager = borrow_resource_manager!(self.resource_of(item.category));
+                    match item.category {
+                        structs::Categories::Weapon => structs::ItemData::Weapon(resource_manager.get_non_fungible_data(&item.id)),
+                        structs::Categories::Armor => structs::ItemData::Armor(resource_manager.get_non_fungible_data(&item.id)),
+                        structs::Categories::Accessory => structs::ItemData::Accessory(resource_manager.get_non_fungible_data(&item.id)),
+                        structs::Categories::Skin => structs::ItemData::Skin(resource_manager.get_non_fungible_data(&item.id)),
+                    }
+                })
+                .collect();
+            (listing, items)
+        }
+        // Marks every expired listing as Expired, and settles English auctions which have ended, so the index only holds live listings.
+        // Returns how many listings were swept
+        pub fn sweep_expired(&mut self) -> u64 {
+            let epoch = Runtime::current_epoch();
+            let mut swept = 0;
+            for id in self.listing_index.clone() {
+                let listing = self.marketplace_listings.get(&id).unwrap().clone();
+                let auction_end = match &listing.kind {
+                    structs::ListingKind::English(auction) => Some(auction.end_epoch),
+                    _ => None,
+                };
+                match auction_end {
+                    Some(end_epoch) if epoch >= end_epoch => {
+                        self.settle_auction(id);
+                        swept += 1;
+                    }
+                    None if listing.is_expired(epoch) => {
+                        self.update_listing(listing, structs::ListingState::Expired);
+                        swept += 1;
+                    }
+                    _ => (),
+                }
+            }
+            swept
+        }
+        // Pays out the royalties a character has earned in a token from sales of items it crafted
+        pub fn claim_royalties(&mut self, character: Proof, token: ResourceAddress) -> Bucket {
+            assert!(character.resource_address() == self.character_nft);
+            let crafter = character.non_fungible_ids().into_iter().next().unwrap();
+            let owed = self.royalties.get(&(crafter.clone(), token)).unwrap_or(dec!(0));
+            self.royalties.insert((crafter, token), dec!(0));
+            Self::token_vault(&mut self.royalty_vaults, token).take(owed)
+        }
+        // Splits a sale payment into the burn, the treasury share and the crafters' royalties. Returns the rest, which is the seller's
+        fn take_fees(&mut self, mut payment: Bucket, items: &Vec<structs::ListedItem>) -> Bucket {
+            let fees = self.game_data.market_fees.clone();
+            let token = payment.resource_address();
+            let price = payment.amount();
+            let burn_bucket: Bucket = payment.take(price * fees.burn_share);
+            Self::token_vault(&mut self.fee_vaults, token).put(payment.take(price * fees.treasury_share));
+            // The royalty is split evenly over the items of a bundle
+            let royalty_share = price * fees.royalty_share / Decimal::from(items.len() as u64);
+            for item in items {
+                // Skins and dropped items have no crafter, so their royalty stays with the seller
+                let crafter = if item.category == structs::Categories::Skin { None } 
+                    else { self.get_item_info(self.resource_of(item.category), &item.id).crafter };
+                if let Some(crafter) = crafter {
+                    let royalty = payment.take(royalty_share);
+                    let owed = self.royalties.get(&(crafter.clone(), token)).unwrap_or(dec!(0));
+                    self.royalties.insert((crafter, token), owed + royalty.amount());
+                    Self::token_vault(&mut self.royalty_vaults, token).put(royalty);
+                }
+            }
+            // XRD can't be burned by the game, so its burn share goes to the treasury
+            if token == RADIX_TOKEN {
+                Self::token_vault(&mut self.fee_vaults, token).put(burn_bucket);
+            }
+            else {
+                self.system_vault.authorize(|| 
+                    burn_bucket.burn());
+            }
+            payment
+        }
+        // Every sale of an NFT on the marketplace, oldest first
+        pub fn get_sale_history(&self, category: structs::Categories, id: NonFungibleId) -> Vec<structs::Sale> {
+            self.sale_history.get(&(category, id)).unwrap_or(Vec::new())
+        }
+        // Appends a sale to the history of every sold NFT
+        fn record_sale(&mut self, items: &Vec<structs::ListedItem>, price: Decimal, payment: ResourceAddress) {
+            for item in items {
+                let key = (item.category, item.id.clone());
+                let mut history = self.sale_history.get(&key).unwrap_or(Vec::new());
+                history.push(structs::Sale { price: price, payment: payment, epoch: Runtime::current_epoch(), bundle_size: items.len() as u64 });
+                self.sale_history.insert(key, history);
+            }
+        }
+        // Moves a listing to a new state, and saves it on the seller's receipt. Only active listings are indexed,
+        // and listings which no longer hold an item are removed
+        fn update_listing(&mut self, mut listing: structs::Listing, state: structs::ListingState) {
+            assert!(listing.receipt.state.can_become(state), "Listing can't go from {:?} to {:?}", listing.receipt.state, state);
+            let key = listing.receipt.id.clone();
+            listing.receipt.state = state;
+            let receipt_data = listing.receipt.clone();
+            self.system_vault.authorize(|| 
+                borrow_resource_manager!(self.receipt_nft)
+                    .update_non_fungible_data(&receipt_data.id, receipt_data.clone()));
+            self.listing_index.retain(|x| *x != key);
+            if state.is_open() {
+                self.marketplace_listings.insert(key, listing);
+            }
+            else {
+                self.marketplace_listings.remove(&key);
+            }
+        }
+        // Order book component which trades a material for gold
+        pub fn get_order_book(&self, material: ResourceAddress) -> ComponentAddress {
+            *self.order_books.get(&material).expect("No order book for this resource")
+        }
+        // Vault of a marketplace token, created the first time the token is used
+        fn token_vault(vaults: &mut HashMap<ResourceAddress, Vault>, token: ResourceAddress) -> &mut Vault {
+            vaults.entry(token).or_insert_with(|| Vault::new(token))
+        }
+        // Takes listed items out of the marketplace vaults, with one bucket per resource
+        fn take_items(&mut self, items: &Vec<structs::ListedItem>) -> Vec<Bucket> {
+            let mut buckets: Vec<Bucket> = Vec::new();
+            for item in items {
+                let nft = self.marketplace_vault(item.category).take_non_fungible(&item.id);
+                match buckets.iter_mut().find(|x| x.resource_address() == nft.resource_address()) {
+                    Some(bucket) => bucket.put(nft),
+                    None => buckets.push(nft),
+                }
+            }
+            buckets
+        }
+        // Marketplace category of any listable NFT resource
+        fn market_category(&self, resource: ResourceAddress) -> structs::Categories {
+            if resource == self.skin_nft { structs::Categories::Skin } else { self.category_of(resource) }
+        }
+        // NFT resource of a marketplace category
+        fn resource_of(&self, category: structs::Categories) -> ResourceAddress {
+            match category {
+                structs::Categories::Weapon => self.weapon_nft,
+                structs::Categories::Armor => self.armor_nft,
+                structs::Categories::Accessory => self.accessory_nft,
+                structs::Categories::Skin => self.skin_nft,
+            }
+        }
+        // Marketplace vault which stores listed items of a category
+        fn marketplace_vault(&mut self, category: structs::Categories) -> &mut Vault {
+            match category {
+                structs::Categories::Weapon => &mut self.marketplace_weapon_vault,
+                structs::Categories::Armor => &mut self.marketplace_armor_vault,
+                structs::Categories::Accessory => &mut self.marketplace_accessory_vault,
+                structs::Categories::Skin => &mut self.marketplace_skin_vault,
+            }
+        }
         // For testing purposes only, allows Proofs to be used in the stage method through the Transaction Manifest
         pub fn full_proof_stage(&mut self, 
             nft_proof: Proof, 
@@ -565,7 +1667,7 @@ blueprint! {
             belt: Proof, 
             shoes: Proof, 
             stage: u64,
-            ) -> (Bucket, Bucket, Bucket) {
+            ) -> (Bucket, Bucket, Bucket, Vec<Bucket>) {
             self.stage(nft_proof, Some(weapon), Some(helmet), Some(chest), Some(pants), Some(gloves), Some(belt), Some(shoes), stage)
         }
         // Place character,weapon,armor, and accessory data + stage # to fight. 
@@ -582,52 +1684,81 @@ blueprint! {
             belt: Option<Proof>, 
             shoes: Option<Proof>, 
             stage: u64,
-            ) -> (Bucket, Bucket, Bucket) {
+            ) -> (Bucket, Bucket, Bucket, Vec<Bucket>) {
             // Data from Proofs
             let mut nft_data: structs::Character = nft_proof.non_fungible().data();
             // Sets gear data. Allows you to fight without any gear. Makes sure you're not using homebrew NFTs
-            let weapon_data = match weapon {
+            let weapon_data: structs::Weapon = match &weapon {
                 Some(weapon) => { 
                     assert!(weapon.resource_address() == self.weapon_nft);
                     weapon.non_fungible().data() },
                 None => { let data = structs::Weapon::null_weapon(); data }
             };
-            let helmet_data = match helmet {
+            let helmet_data: structs::Armor = match &helmet {
                 Some(helmet) => {
                     assert!(helmet.resource_address() == self.armor_nft);
                     helmet.non_fungible().data()},
                 None => { let data = structs::Armor::null_armor(structs::ArmorNames::Helmet); data }
             };
-            let chest_data = match chest {
+            let chest_data: structs::Armor = match &chest {
                 Some(chest) =>{ 
                     assert!(chest.resource_address() == self.armor_nft);
                     chest.non_fungible().data()},
                 None => { let data = structs::Armor::null_armor(structs::ArmorNames::Chest); data }
             };
-            let pants_data = match pants {
+            let pants_data: structs::Armor = match &pants {
                 Some(pants) => {
                     assert!(pants.resource_address() == self.armor_nft);
                     pants.non_fungible().data()},
                 None => { let data = structs::Armor::null_armor(structs::ArmorNames::Pants); data }
             };
-            let gloves_data = match gloves {
+            let gloves_data: structs::Accessory = match &gloves {
                 Some(gloves) => {
                     assert!(gloves.resource_address() == self.accessory_nft);
                     gloves.non_fungible().data()},
                 None => { let data = structs::Accessory::null_accessory(structs::AccessoryNames::Gloves); data }
             };
-            let belt_data = match belt {
+            let belt_data: structs::Accessory = match &belt {
                 Some(belt) => {
                     assert!(belt.resource_address() == self.accessory_nft);
                     belt.non_fungible().data()},
                 None => { let data = structs::Accessory::null_accessory(structs::AccessoryNames::Belt); data }
             };
-            let shoes_data = match shoes {
+            let shoes_data: structs::Accessory = match &shoes {
                 Some(shoes) => {
                     assert!(shoes.resource_address() == self.accessory_nft);
                     shoes.non_fungible().data()},
                 None => { let data = structs::Accessory::null_accessory(structs::AccessoryNames::Shoes); data }
             };
+            // Makes sure the character meets the requirements of all gear. Null gear has no requirements
+            if weapon_data.item_info.level > 0 {
+                let mut requirements = self.game_data.weapon_data.get(&(weapon_data.class, weapon_data.item_info.id)).unwrap().requirements.clone();
+                if requirements.classes.is_empty() {
+                    requirements.classes.push(weapon_data.class);
+                }
+                self.check_requirements("Weapon", &requirements, &nft_data);
+            }
+            for armor in [&helmet_data, &chest_data, &pants_data] {
+                if armor.item_info.level > 0 {
+                    let requirements = &self.game_data.armor_data.get(&(armor.part, armor.item_info.id)).unwrap().requirements;
+                    self.check_requirements("Armor", requirements, &nft_data);
+                }
+            }
+            for accessory in [&gloves_data, &belt_data, &shoes_data] {
+                if accessory.item_info.level > 0 {
+                    let requirements = &self.game_data.accessory_data.get(&(accessory.part, accessory.item_info.id)).unwrap().requirements;
+                    self.check_requirements("Accessory", requirements, &nft_data);
+                }
+            }
+            // Broken gear only keeps a portion of its stats
+            let broken_multiplier = self.game_data.durability_data.broken_multiplier;
+            let weapon_data = weapon_data.effective(broken_multiplier);
+            let helmet_data = helmet_data.effective(broken_multiplier);
+            let chest_data = chest_data.effective(broken_multiplier);
+            let pants_data = pants_data.effective(broken_multiplier);
+            let gloves_data = gloves_data.effective(broken_multiplier);
+            let belt_data = belt_data.effective(broken_multiplier);
+            let shoes_data = shoes_data.effective(broken_multiplier);
             // Getting data of selected stage:
             let data = self.game_data.stage_data.get(&stage).unwrap().clone();
             let enemy_1_data = &data[0];
@@ -642,24 +1773,54 @@ blueprint! {
             assert!(gloves_data.part == structs::AccessoryNames::Gloves);
             assert!(belt_data.part == structs::AccessoryNames::Belt);
             assert!(shoes_data.part == structs::AccessoryNames::Shoes);
-            // Speed = sum of character + gear speed/Speed penality (Tier1 gear gives 1% penalty per item for 7% total penalty)
-            let speed = (nft_data.speed + gloves_data.speed + belt_data.speed + shoes_data.speed) * (dec!(1) - weapon_data.item_info.weight -
-                helmet_data.item_info.weight - chest_data.item_info.weight - pants_data.item_info.weight - 
-                gloves_data.item_info.weight - belt_data.item_info.weight - shoes_data.item_info.weight);
+            // Affix modifiers and set bonuses of all gear are added together, then applied as a % bonus to each stat
+            let mut modifiers = structs::Item::modifier_totals(vec![&weapon_data.item_info, &helmet_data.item_info, &chest_data.item_info, 
+                &pants_data.item_info, &gloves_data.item_info, &belt_data.item_info, &shoes_data.item_info]);
+            let set_bonuses = self.set_bonuses(vec![
+                (structs::Categories::Weapon, &weapon_data.item_info),
+                (structs::Categories::Armor, &helmet_data.item_info),
+                (structs::Categories::Armor, &chest_data.item_info),
+                (structs::Categories::Armor, &pants_data.item_info),
+                (structs::Categories::Accessory, &gloves_data.item_info),
+                (structs::Categories::Accessory, &belt_data.item_info),
+                (structs::Categories::Accessory, &shoes_data.item_info),
+            ]);
+            for (name, value) in set_bonuses {
+                *modifiers.entry(name).or_insert(0) += value;
+            }
+            let modifier = |name: structs::Modifier| -> Decimal {
+                let total = *modifiers.get(&name).unwrap_or(&0) as i128;
+                let dec: Decimal = total.into();
+                dec / dec!(100)
+            };
+            // Socketed gems add a flat bonus to the character's stats
+            let gems = structs::Item::gem_totals(vec![&weapon_data.item_info, &helmet_data.item_info, &chest_data.item_info, 
+                &pants_data.item_info, &gloves_data.item_info, &belt_data.item_info, &shoes_data.item_info]);
+            let gem = |stat: structs::Stats| -> Decimal { *gems.get(&stat).unwrap_or(&dec!(0)) };
+            // Speed = sum of character + gear speed * encumbrance penalty. Penalty follows the encumbrance curve for gear weight / carry capacity
+            let weight = weapon_data.item_info.weight + helmet_data.item_info.weight + chest_data.item_info.weight + pants_data.item_info.weight + 
+                gloves_data.item_info.weight + belt_data.item_info.weight + shoes_data.item_info.weight;
+            assert!(weight >= dec!(0), "Gear weight can't be negative");
+            let encumbrance_info = &self.game_data.encumbrance_data;
+            let level: Decimal = nft_data.level.into();
+            let capacity = encumbrance_info.base_capacity + encumbrance_info.capacity_per_level * level;
+            let encumbrance = combat::encumbrance(weight / capacity, &encumbrance_info.curve, encumbrance_info.floor);
+            let speed = (nft_data.speed + gem(structs::Stats::Speed) + gloves_data.speed + belt_data.speed + shoes_data.speed) * encumbrance 
+                * (dec!(1) + modifier(structs::Modifier::Speed));
             // Defense = structs::Character defense * gear buff
             let defense = {
-                nft_data.defense * helmet_data.defense * chest_data.defense * pants_data.defense
+                (nft_data.defense + gem(structs::Stats::Defense)) * helmet_data.defense * chest_data.defense * pants_data.defense * (dec!(1) + modifier(structs::Modifier::Defense))
             };
             // Attack = structs::Character attack * gear buff
             let attack = {
-                nft_data.attack * gloves_data.attack * belt_data.attack * shoes_data.attack
+                (nft_data.attack + gem(structs::Stats::Attack)) * gloves_data.attack * belt_data.attack * shoes_data.attack * (dec!(1) + modifier(structs::Modifier::Attack))
             };
             // Magic = structs::Character magic * gear buff
             let magic = {
-                nft_data.magic * gloves_data.magic * belt_data.magic * shoes_data.magic
+                (nft_data.magic + gem(structs::Stats::Magic)) * gloves_data.magic * belt_data.magic * shoes_data.magic * (dec!(1) + modifier(structs::Modifier::Magic))
             };
             // Health, like Speed, is simply added together. However, there are no penalties for Health like Speed
-            let health = nft_data.health + helmet_data.health + chest_data.health + pants_data.health;
+            let health = (nft_data.health + gem(structs::Stats::Health) + helmet_data.health + chest_data.health + pants_data.health) * (dec!(1) + modifier(structs::Modifier::Health));
             let damage: Decimal = 
                 (weapon_data.physical_base + (weapon_data.physical_scaling * attack)) +
                 (weapon_data.spell_base + (weapon_data.spell_scaling * magic));
@@ -680,6 +1841,7 @@ blueprint! {
                 damage: damage,
                 defense: defense,
                 speed: speed,
+                lifesteal: modifier(structs::Modifier::Lifesteal) * dec!(100),
                 ability_weapon: weapon_ability,
                 ability_gloves: gloves_ability,
                 ability_belt: belt_ability,
@@ -692,7 +1854,7 @@ blueprint! {
             let fight2 = combat::combat(player_info, enemy_2_data.combat_info);
             player_info.health = fight2;
             let fight3 = combat::combat(player_info, enemy_3_data.combat_info);
-            // Numbers which drop can be randomized as well with a minor addition
+            // Fixed rewards, loot tables are rolled on top
             let rewards = if fight == dec!(0) || fight <= dec!(0) {
                 let exp = enemy_1_data.exp_on_loss;
                 let gold = enemy_1_data.gold_on_loss;
@@ -711,29 +1873,121 @@ blueprint! {
                 let exp = enemy_1_data.exp_on_win + enemy_2_data.exp_on_win + enemy_3_data.exp_on_loss;
                 let gold = enemy_1_data.gold_on_win + enemy_2_data.gold_on_win + enemy_3_data.gold_on_loss;
                 let greavite = enemy_1_data.greavite_on_win + enemy_2_data.greavite_on_win + enemy_3_data.greavite_on_loss;
-                let wood = enemy_1_data.wood_on_win + enemy_2_data.wood_on_win + enemy_2_data.wood_on_loss;
+                let wood = enemy_1_data.wood_on_win + enemy_2_data.wood_on_win + enemy_3_data.wood_on_loss;
                 (exp,gold,greavite,wood)
             }
             else {
                 let exp = enemy_1_data.exp_on_win + enemy_2_data.exp_on_win + enemy_3_data.exp_on_win;
                 let gold = enemy_1_data.gold_on_win + enemy_2_data.gold_on_win + enemy_3_data.gold_on_win;
                 let greavite = enemy_1_data.greavite_on_win + enemy_2_data.greavite_on_win + enemy_3_data.greavite_on_win;
-                let wood = enemy_1_data.wood_on_win + enemy_2_data.wood_on_win + enemy_2_data.wood_on_win;
+                let wood = enemy_1_data.wood_on_win + enemy_2_data.wood_on_win + enemy_3_data.wood_on_win;
                 (exp,gold,greavite,wood)
             };
-            nft_data.exp += rewards.0;
+            // Gold and EXP find modifiers increase the rewards
+            let exp_find = *modifiers.get(&structs::Modifier::ExpFind).unwrap_or(&0);
+            nft_data.exp += rewards.0 + rewards.0 * exp_find / 100;
+            // Rolls the loot table of every enemy fought
+            let fights = if fight <= dec!(0) { 1 } else if fight2 <= dec!(0) { 2 } else { 3 };
+            let mut loot = (Decimal::from(rewards.1), Decimal::from(rewards.2), Decimal::from(rewards.3));
+            let mut drops: Vec<Bucket> = Vec::new();
+            for (enemy, result) in [fight, fight2, fight3].iter().enumerate().take(fights as usize) {
+                if let Some(table) = self.game_data.loot_tables.get(&(stage, enemy as u64)) {
+                    let entries = if *result > dec!(0) { &table.on_win } else { &table.on_loss };
+                    self.roll_loot(entries, &mut loot, &mut drops);
+                }
+            }
+            let gold: Decimal = loot.0 * (dec!(1) + modifier(structs::Modifier::GoldFind));
                 let reward1 = self.system_vault.authorize(||
                     borrow_resource_manager!(self.token_gold)
-                        .mint(rewards.1));
+                        .mint(gold));
                 let reward2 = self.system_vault.authorize(||
                         borrow_resource_manager!(self.token_wood)
-                        .mint(rewards.2));
+                        .mint(loot.2));
                 let reward3 = self.system_vault.authorize(||
                     borrow_resource_manager!(self.token_greavite)
-                        .mint(rewards.3));
+                        .mint(loot.1));
+                // Worn gear loses 1 durability for each fight it took part in
+                for proof in [&weapon, &helmet, &chest, &pants, &gloves, &belt, &shoes] {
+                    if let Some(proof) = proof {
+                        let id = proof.non_fungible_ids().into_iter().next().unwrap();
+                        let mut info = self.get_item_info(proof.resource_address(), &id);
+                        info.wear(fights);
+                        self.set_item_info(proof.resource_address(), &id, info);
+                    }
+                }
+                // Enemies beaten count towards achievements and quests, and beating all 3 clears the stage
+                let wins = if fight3 > dec!(0) { 3 } else { fights - 1 };
+                self.record(&mut nft_data, structs::Task::DefeatEnemies(stage), wins);
+                if wins == 3 {
+                    self.record(&mut nft_data, structs::Task::ClearStage(stage), 1);
+                }
                 nft_data = self.levelup(nft_data.clone());
+                self.unlock_achievements(&mut nft_data);
                 self.system_vault.authorize(|| nft_proof.non_fungible().update_data(nft_data));
-                return (reward1, reward2, reward3)
+                return (reward1, reward2, reward3, drops)
+        }
+        // Rolls every entry of a loot table. Materials are added to loot as (Gold, Greavite, Wood), NFTs and catalysts go in drops
+        fn roll_loot(&self, entries: &Vec<structs::LootEntry>, loot: &mut (Decimal, Decimal, Decimal), drops: &mut Vec<Bucket>) {
+            for entry in entries {
+                if rng::seed(0, 10000) >= entry.odds {
+                    continue;
+                }
+                let amount = rng::seed(entry.min as u128, entry.max as u128 + 1) as u64;
+                match entry.loot {
+                    structs::Loot::Gold => loot.0 += Decimal::from(amount),
+                    structs::Loot::Greavite => loot.1 += Decimal::from(amount),
+                    structs::Loot::Wood => loot.2 += Decimal::from(amount),
+                    structs::Loot::Catalyst => drops.push(self.system_vault.authorize(|| 
+                        borrow_resource_manager!(self.token_catalyst)
+                            .mint(amount))),
+                    structs::Loot::Item(template, min_roll, max_roll) => {
+                        for _ in 0..amount {
+                            drops.push(self.mint_item(template, min_roll, max_roll, None));
+                        }
+                    }
+                    structs::Loot::RecipeScroll(recipe_id) => {
+                        for _ in 0..amount {
+                            drops.push(self.system_vault.authorize(|| 
+                                borrow_resource_manager!(self.scroll_nft)
+                                    .mint_non_fungible(&NonFungibleId::random(), structs::RecipeScroll { recipe_id })));
+                        }
+                    }
+                }
+            }
+        }
+        // Panics with a message naming the failed requirement, if the character can't use the item
+        fn check_requirements(&self, gear: &str, requirements: &structs::Requirements, character: &structs::Character) {
+            assert!(requirements.classes.is_empty() || requirements.classes.contains(&character.class),
+                "{} can only be used by classes {:?}, character is class {}", gear, requirements.classes, character.class);
+            assert!(character.level >= requirements.min_level,
+                "{} requires character level {}, character is level {}", gear, requirements.min_level, character.level);
+            if let Some((stat, minimum)) = requirements.min_stat {
+                let value = match stat {
+                    structs::Stats::Health => character.health,
+                    structs::Stats::Attack => character.attack,
+                    structs::Stats::Magic => character.magic,
+                    structs::Stats::Defense => character.defense,
+                    structs::Stats::Speed => character.speed,
+                };
+                assert!(value >= minimum,
+                    "{} requires {} {:?}, character has {}", gear, minimum, stat, value);
+            }
+        }
+        // Counts the worn pieces of every set, and sums the modifiers of each set bonus reached
+        fn set_bonuses(&self, worn: Vec<(structs::Categories, &structs::Item)>) -> HashMap<structs::Modifier, u128> {
+            let mut totals: HashMap<structs::Modifier, u128> = HashMap::new();
+            for set in self.game_data.set_data.values() {
+                // Null gear is level 0, and never counts towards a set
+                let pieces = worn.iter()
+                    .filter(|x| x.1.level > 0 && set.pieces.contains(&(x.0, x.1.id)))
+                    .count() as u64;
+                for (required, name, value) in set.bonuses.iter() {
+                    if pieces >= *required {
+                        *totals.entry(*name).or_insert(0) += value;
+                    }
+                }
+            }
+            totals
         }
         //Levelup method
         pub fn levelup(&mut self, nft_data: structs::Character) -> structs::Character {
@@ -752,17 +2006,14 @@ blueprint! {
             loop {
                 if nft_data.exp >= self.game_data.exp_data[level] { 
                     new_data = structs::Character {
-                        name: nft_data.name.clone(),
-                        class: nft_data.class,
                         level: level as u64 + 1,
-                        exp: nft_data.exp,
-                        stage: nft_data.stage, 
                         health: hp[level].into(),
                         attack: atk[level].into(),
                         magic: mag[level].into(),
                         defense: def[level].into(),
                         speed: spd[level].into(),
                         version: self.game_data.game_version,
+                        ..nft_data.clone()
                     };
                     level += 1;
                     continue; 
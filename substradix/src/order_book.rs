@@ -0,0 +1,205 @@
+use scrypto::prelude::*;
+use super::structs;
+
+// Limit order book trading a material for gold. Orders fill by price, then by time placed,
+// and trade at the price of the order which was resting on the book
+blueprint! {
+    struct OrderBook {
+        system_vault: Vault, // Stores the badge which mints and updates order receipts
+        order_nft: ResourceAddress, // Resource address of the order receipt NFT
+        material_vault: Vault, // Stores material of sell orders, and material bought by buy orders
+        gold_vault: Vault, // Stores gold of buy orders, and gold earned by sell orders
+        bids: Vec<structs::BookOrder>, // Open buy orders, highest price first
+        asks: Vec<structs::BookOrder>, // Open sell orders, lowest price first
+        sequence: u64, // Number of orders placed
+        last_price: Option<Decimal>, // Price of the latest trade
+    }
+
+    impl OrderBook {
+        pub fn new(material: ResourceAddress, gold: ResourceAddress) -> ComponentAddress {
+            let system_badge = ResourceBuilder::new_fungible()
+                .metadata("name", "order book system")
+                .divisibility(DIVISIBILITY_NONE)
+                .initial_supply(1);
+            let system_rule: AccessRule = rule!(require(system_badge.resource_address()));
+            let order_nft = ResourceBuilder::new_non_fungible()
+                .metadata("type", "Substradix order NFT")
+                .mintable(system_rule.clone(), LOCKED)
+                .burnable(system_rule.clone(), LOCKED)
+                .restrict_withdraw(AccessRule::AllowAll, LOCKED)
+                .updateable_non_fungible_data(system_rule.clone(), LOCKED)
+                .no_initial_supply();
+
+            Self {
+                system_vault: Vault::with_bucket(system_badge),
+                order_nft,
+                material_vault: Vault::new(material),
+                gold_vault: Vault::new(gold),
+                bids: Vec::new(),
+                asks: Vec::new(),
+                sequence: 0,
+                last_price: None,
+            }
+            .instantiate()
+            .globalize()
+        }
+        // Places a limit order for an amount of material at a gold price per unit. Sell orders escrow the material, and buy orders the gold.
+        // The order fills against the book right away where it can, and the rest stays on the book.
+        // Returns the order receipt, and what the order filled for right away along with change of the payment, as (material, gold)
+        pub fn place_order(&mut self, mut payment: Bucket, side: structs::Side, price: Decimal, amount: Decimal) -> (Bucket, Bucket, Bucket) {
+            assert!(price > dec!(0), "Price must be positive");
+            assert!(amount > dec!(0), "Amount must be positive");
+            let escrow = match side {
+                structs::Side::Sell => {
+                    assert!(payment.resource_address() == self.material_vault.resource_address());
+                    self.material_vault.put(payment.take(amount));
+                    amount
+                }
+                structs::Side::Buy => {
+                    assert!(payment.resource_address() == self.gold_vault.resource_address());
+                    self.gold_vault.put(payment.take(amount * price));
+                    amount * price
+                }
+            };
+            let id = NonFungibleId::random();
+            let receipt_data = structs::OrderReceipt {
+                side: side,
+                price: price,
+                amount: amount,
+                remaining: amount,
+                escrow: escrow,
+                proceeds: dec!(0),
+            };
+            let receipt = self.system_vault.authorize(||
+                borrow_resource_manager!(self.order_nft)
+                    .mint_non_fungible(&id, receipt_data));
+            let order = structs::BookOrder {
+                id: id.clone(),
+                price: price,
+                sequence: self.sequence,
+            };
+            self.sequence += 1;
+            // Orders at the same price keep the order they were placed in
+            match side {
+                structs::Side::Buy => {
+                    let position = self.bids.iter().position(|x| x.price < price).unwrap_or(self.bids.len());
+                    self.bids.insert(position, order);
+                }
+                structs::Side::Sell => {
+                    let position = self.asks.iter().position(|x| x.price > price).unwrap_or(self.asks.len());
+                    self.asks.insert(position, order);
+                }
+            }
+            self.match_orders();
+            let (mut material, mut gold) = self.claim(&id);
+            match side {
+                structs::Side::Sell => material.put(payment),
+                structs::Side::Buy => gold.put(payment),
+            }
+            (receipt, material, gold)
+        }
+        // Takes an order off the book. Returns its escrow and unclaimed fills, as (material, gold)
+        pub fn cancel_order(&mut self, receipt: Bucket) -> (Bucket, Bucket) {
+            assert!(receipt.resource_address() == self.order_nft);
+            let id = receipt.non_fungible::<structs::OrderReceipt>().id();
+            self.bids.retain(|x| x.id != id);
+            self.asks.retain(|x| x.id != id);
+            let (mut material, mut gold) = self.claim(&id);
+            // Read after claiming, as fully filled buy orders have their escrow claimed too
+            let receipt_data = self.receipt_data(&id);
+            match receipt_data.side {
+                structs::Side::Sell => material.put(self.material_vault.take(receipt_data.escrow)),
+                structs::Side::Buy => gold.put(self.gold_vault.take(receipt_data.escrow)),
+            }
+            self.system_vault.authorize(||
+                receipt.burn());
+            (material, gold)
+        }
+        // Pays out what an order has filled for so far. Fully filled buy orders also get back gold left over from trading below their price.
+        // Returns (material, gold)
+        pub fn claim_order(&mut self, receipt: Proof) -> (Bucket, Bucket) {
+            assert!(receipt.resource_address() == self.order_nft);
+            let id = receipt.non_fungible_ids().into_iter().next().unwrap();
+            self.claim(&id)
+        }
+        // Open amount at each of the best prices on both sides, and the price of the latest trade
+        pub fn get_depth(&self, levels: u64) -> structs::BookDepth {
+            structs::BookDepth {
+                bids: self.depth(&self.bids, levels),
+                asks: self.depth(&self.asks, levels),
+                last_price: self.last_price,
+            }
+        }
+        // Trades the best buy and sell orders against each other while their prices cross
+        fn match_orders(&mut self) {
+            while !self.bids.is_empty() && !self.asks.is_empty() && self.bids[0].price >= self.asks[0].price {
+                let bid = self.bids[0].clone();
+                let ask = self.asks[0].clone();
+                let price = if bid.sequence < ask.sequence { bid.price } else { ask.price };
+                let mut buyer = self.receipt_data(&bid.id);
+                let mut seller = self.receipt_data(&ask.id);
+                let amount = if buyer.remaining < seller.remaining { buyer.remaining } else { seller.remaining };
+                let cost = amount * price;
+                buyer.remaining -= amount;
+                buyer.escrow -= cost;
+                buyer.proceeds += amount;
+                seller.remaining -= amount;
+                seller.escrow -= amount;
+                seller.proceeds += cost;
+                if buyer.remaining == dec!(0) {
+                    self.bids.remove(0);
+                }
+                if seller.remaining == dec!(0) {
+                    self.asks.remove(0);
+                }
+                self.update_receipt(&bid.id, buyer);
+                self.update_receipt(&ask.id, seller);
+                self.last_price = Some(price);
+            }
+        }
+        // Takes the unclaimed fills of an order out of the vaults, as (material, gold)
+        fn claim(&mut self, id: &NonFungibleId) -> (Bucket, Bucket) {
+            let mut receipt_data = self.receipt_data(id);
+            let mut material = Bucket::new(self.material_vault.resource_address());
+            let mut gold = Bucket::new(self.gold_vault.resource_address());
+            match receipt_data.side {
+                structs::Side::Sell => gold.put(self.gold_vault.take(receipt_data.proceeds)),
+                structs::Side::Buy => {
+                    material.put(self.material_vault.take(receipt_data.proceeds));
+                    if receipt_data.remaining == dec!(0) {
+                        gold.put(self.gold_vault.take(receipt_data.escrow));
+                        receipt_data.escrow = dec!(0);
+                    }
+                }
+            }
+            receipt_data.proceeds = dec!(0);
+            self.update_receipt(id, receipt_data);
+            (material, gold)
+        }
+        // Sums the open amount of orders at each price, for the best levels of one side
+        fn depth(&self, orders: &Vec<structs::BookOrder>, levels: u64) -> Vec<(Decimal, Decimal)> {
+            let mut depth: Vec<(Decimal, Decimal)> = Vec::new();
+            for order in orders {
+                let remaining = self.receipt_data(&order.id).remaining;
+                match depth.last_mut() {
+                    Some(level) if level.0 == order.price => level.1 += remaining,
+                    _ => {
+                        if depth.len() as u64 == levels {
+                            break;
+                        }
+                        depth.push((order.price, remaining));
+                    }
+                }
+            }
+            depth
+        }
+        fn receipt_data(&self, id: &NonFungibleId) -> structs::OrderReceipt {
+            borrow_resource_manager!(self.order_nft).get_non_fungible_data(id)
+        }
+        fn update_receipt(&self, id: &NonFungibleId, receipt_data: structs::OrderReceipt) {
+            self.system_vault.authorize(||
+                borrow_resource_manager!(self.order_nft)
+                    .update_non_fungible_data(id, receipt_data));
+        }
+    }
+}
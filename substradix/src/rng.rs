@@ -18,3 +18,17 @@ pub fn seed_decimal(min: u128, max: u128, float: Decimal) -> Decimal {
     let dec: Decimal = int.into();
     dec / float
 }
+
+
+// Picks an index, with odds proportional to the weight at that index
+pub fn weighted_index(weights: Vec<u128>) -> usize {
+    let total: u128 = weights.iter().sum();
+    let mut roll = seed(0, total);
+    for (index, weight) in weights.iter().enumerate() {
+        if roll < *weight {
+            return index
+        }
+        roll -= weight;
+    }
+    weights.len() - 1
+}
\ No newline at end of file
@@ -9,15 +9,319 @@ pub struct Item {
     pub version: Decimal,
     pub weight: Decimal,
     pub level: u64, 
+    pub rarity: Rarity,
+    pub affix_slots: u64,
+    pub affixes: Vec<Affix>,
+    pub durability: u64,
+    pub max_durability: u64,
+    // Gems socketed into the item
+    pub sockets: Vec<Gem>,
+    pub skin: Option<AppliedSkin>,
+    // Character id of the crafter, who earns royalties on marketplace sales. None for dropped items
+    pub crafter: Option<NonFungibleId>,
+}
+
+impl Item {
+    pub fn null_item() -> Self {
+        Self {
+            id: dec!(0),
+            version: dec!(1),
+            weight: dec!(0),
+            level: 0,
+            rarity: Rarity::Common,
+            affix_slots: 0,
+            affixes: Vec::new(),
+            durability: 0,
+            max_durability: 0,
+            sockets: Vec::new(),
+            skin: None,
+            crafter: None,
+        }
+    }
+    // Null items have no durability, so they never break
+    pub fn is_broken(&self) -> bool {
+        self.max_durability > 0 && self.durability == 0
+    }
+    pub fn wear(&mut self, amount: u64) {
+        self.durability = self.durability.saturating_sub(amount);
+    }
+    // Sums the affix values of all given items by modifier
+    pub fn modifier_totals(items: Vec<&Item>) -> HashMap<Modifier, u128> {
+        let mut totals: HashMap<Modifier, u128> = HashMap::new();
+        for item in items {
+            for affix in item.affixes.iter() {
+                *totals.entry(affix.modifier).or_insert(0) += affix.value;
+            }
+        }
+        totals
+    }
+    // Sums the socketed gem values of all given items by stat
+    pub fn gem_totals(items: Vec<&Item>) -> HashMap<Stats, Decimal> {
+        let mut totals: HashMap<Stats, Decimal> = HashMap::new();
+        for item in items {
+            for gem in item.sockets.iter() {
+                *totals.entry(gem.stat).or_insert(dec!(0)) += gem.value;
+            }
+        }
+        totals
+    }
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Affix {
+    pub affix_id: u64,
+    pub kind: AffixKind,
+    pub modifier: Modifier,
+    pub value: u128,
 }
 
 #[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
 pub struct Receipt {
     #[scrypto(mutable)]
     pub price: Decimal,
+    // Token the price is set in, and the seller is paid in
+    pub payment: ResourceAddress,
     pub id: NonFungibleId, 
+    // Every NFT in the listing. Bundles hold several, and are always sold together
+    pub items: Vec<ListedItem>,
+    #[scrypto(mutable)]
+    pub state: ListingState,
+    // What the seller redeems, set when sold
+    #[scrypto(mutable)]
+    pub payout: Decimal,
+    // Character id of the seller, who is credited with the sale on redemption
+    pub seller: NonFungibleId,
+}
+
+// One NFT held by a listing. Skins have no rarity, and are always listed as common
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct ListedItem {
     pub category: Categories,
-    pub item_id: NonFungibleId
+    pub id: NonFungibleId,
+    pub rarity: Rarity,
+}
+
+// Tokens escrowed on an auction. Refunded if outbid, or redeemed for the items if it wins
+#[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Bid {
+    pub listing_id: NonFungibleId,
+    pub items: Vec<ListedItem>,
+    pub amount: Decimal,
+    pub payment: ResourceAddress,
+    // Set when the auction is settled with this bid as the winner
+    #[scrypto(mutable)]
+    pub won: bool,
+}
+
+// Tokens escrowed for buying an item. Redeemed for the item once accepted, or refunded if not
+#[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Offer {
+    pub target: OfferTarget,
+    pub price: Decimal,
+    pub payment: ResourceAddress,
+    // Id of the item the offer was accepted with
+    #[scrypto(mutable)]
+    pub filled: Option<NonFungibleId>,
+    // Character id of the seller which accepted the offer, who is credited with the sale
+    #[scrypto(mutable)]
+    pub seller: Option<NonFungibleId>,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub enum OfferTarget {
+    // A specific NFT
+    Item(Categories, NonFungibleId),
+    // Any Weapon/Armor/Accessory of the category with this item id, at or above the level
+    Criteria(Categories, Decimal, u64),
+}
+
+// Marketplace listing, keyed by the id of its receipt
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Listing {
+    pub receipt: Receipt,
+    pub kind: ListingKind,
+    // Character id of the seller
+    pub seller: NonFungibleId,
+    // Epoch after which the listing can't be bought. English auctions end at their end epoch instead
+    pub expiry_epoch: Option<u64>,
+}
+
+impl Listing {
+    // Price a buyer would pay now. For English auctions, the highest bid or the start bid if there are none
+    pub fn current_price(&self, epoch: u64) -> Decimal {
+        match &self.kind {
+            ListingKind::Fixed => self.receipt.price,
+            ListingKind::English(auction) => match &auction.highest_bid {
+                Some((_, amount)) => *amount,
+                None => auction.start_bid,
+            },
+            ListingKind::Dutch(auction) => auction.price(epoch),
+        }
+    }
+    pub fn is_expired(&self, epoch: u64) -> bool {
+        self.expiry_epoch.map_or(false, |expiry| epoch >= expiry)
+    }
+}
+
+// Active -> Sold -> Redeemed when bought and the proceeds redeemed. Redeemed receipts stay with the seller as a record of the sale
+// Active -> Expired -> Cancelled when an auction ends unsold, or a fixed price or Dutch listing passes its expiry epoch, and the item is taken back
+// Active -> Cancelled when the seller removes the listing
+// Only Active and Expired listings are kept on the marketplace, sold listings are redeemed with the receipt alone
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, PartialEq, Debug)]
+pub enum ListingState {
+    Active,
+    Sold,
+    Cancelled,
+    Expired,
+    Redeemed,
+}
+
+impl ListingState {
+    pub fn can_become(&self, next: ListingState) -> bool {
+        match (self, next) {
+            (ListingState::Active, ListingState::Sold) => true,
+            (ListingState::Active, ListingState::Cancelled) => true,
+            (ListingState::Active, ListingState::Expired) => true,
+            (ListingState::Expired, ListingState::Cancelled) => true,
+            (ListingState::Sold, ListingState::Redeemed) => true,
+            _ => false,
+        }
+    }
+    // Listing still holds its item on the marketplace
+    pub fn is_open(&self) -> bool {
+        matches!(self, ListingState::Active | ListingState::Expired)
+    }
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, PartialEq)]
+pub struct Sale {
+    // Price of the whole listing, which is shared by every item of a bundle
+    pub price: Decimal,
+    pub payment: ResourceAddress,
+    pub epoch: u64,
+    pub bundle_size: u64,
+}
+
+// Filters for listing queries. None matches everything, and bundles match if any of their items does
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct ListingFilter {
+    pub category: Option<Categories>,
+    // Prices are only comparable within a payment token
+    pub payment: Option<ResourceAddress>,
+    pub min_price: Option<Decimal>,
+    pub max_price: Option<Decimal>,
+    // Skins have no level, and never match a level filter
+    pub min_level: Option<u64>,
+    pub max_level: Option<u64>,
+    pub seller: Option<NonFungibleId>,
+}
+
+// Full data of a listed NFT
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub enum ItemData {
+    Weapon(Weapon),
+    Armor(Armor),
+    Accessory(Accessory),
+    Skin(Skin),
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub enum ListingKind {
+    // Sold at the receipt price to the first buyer
+    Fixed,
+    English(Auction),
+    // Sold at the current price of the auction to the first buyer
+    Dutch(DutchAuction),
+}
+
+// Ascending auction. Bids placed within the extension window of the end push the end back
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Auction {
+    pub start_bid: Decimal,
+    // Auction is only sold if the highest bid reaches the reserve price
+    pub reserve_price: Decimal,
+    pub min_increment: Decimal,
+    pub end_epoch: u64,
+    // (Bid NFT id, amount) of the highest bid
+    pub highest_bid: Option<(NonFungibleId, Decimal)>,
+}
+
+// Descending auction. The price falls from the start to the floor over the duration, then stays at the floor
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct DutchAuction {
+    pub start_price: Decimal,
+    pub floor_price: Decimal,
+    pub start_epoch: u64,
+    pub duration: u64,
+    pub decay: Decay,
+}
+
+impl DutchAuction {
+    pub fn price(&self, epoch: u64) -> Decimal {
+        let elapsed = epoch - self.start_epoch;
+        if elapsed >= self.duration {
+            return self.floor_price
+        }
+        let above_floor = self.start_price - self.floor_price;
+        match self.decay {
+            Decay::Linear => {
+                let elapsed: Decimal = elapsed.into();
+                let duration: Decimal = self.duration.into();
+                self.start_price - above_floor * elapsed / duration
+            }
+            Decay::Exponential(percent) => {
+                // kept ^ elapsed by squaring, so the cost grows with the log of the elapsed epochs
+                let mut kept = dec!(1) - Decimal::from(percent as i128) / dec!(100);
+                let mut factor = dec!(1);
+                let mut remaining = elapsed;
+                while remaining > 0 {
+                    if remaining % 2 == 1 {
+                        factor = factor * kept;
+                    }
+                    kept = kept * kept;
+                    remaining /= 2;
+                }
+                self.floor_price + above_floor * factor
+            }
+        }
+    }
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, PartialEq)]
+pub enum Decay {
+    Linear,
+    // Price above the floor drops by this % every epoch
+    Exponential(u128),
+}
+
+#[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Gem {
+    pub id: Decimal,
+    pub stat: Stats,
+    // Flat bonus added to the stat
+    pub value: Decimal,
+}
+
+// Crafts a recipe which needs a scroll. Burned on craft
+#[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct RecipeScroll {
+    pub recipe_id: u64,
+}
+
+// Cosmetic only, skins never change stats
+#[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct Skin {
+    pub name: String,
+    // Key of the skin's image, for wallets and clients to display
+    pub image_key: String,
+    pub target: SkinTarget,
+}
+
+// Skin applied to a Character/Weapon/Armor/Accessory NFT. The skin NFT is held by the component until removed
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct AppliedSkin {
+    pub skin_id: NonFungibleId,
+    pub image_key: String,
 }
 
 #[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
@@ -44,6 +348,30 @@ pub struct Character {
     pub speed: Decimal,
     #[scrypto(mutable)]
     pub version: Decimal,
+    #[scrypto(mutable)]
+    pub skin: Option<AppliedSkin>,
+    // Unlocked titles, in the order they were earned
+    #[scrypto(mutable)]
+    pub titles: Vec<String>,
+    // Progress towards achievements
+    #[scrypto(mutable)]
+    pub counters: HashMap<Counter, u64>,
+    #[scrypto(mutable)]
+    pub achievements: Vec<u64>,
+    // Achievements whose rewards have been claimed
+    #[scrypto(mutable)]
+    pub claimed_achievements: Vec<u64>,
+    // Progress of each quest, only counted within the current reset window
+    #[scrypto(mutable)]
+    pub quests: HashMap<u64, QuestProgress>,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, PartialEq)]
+pub struct QuestProgress {
+    // Window the progress was made in, current epoch / reset epochs of the quest
+    pub window: u64,
+    pub progress: u64,
+    pub claimed: bool,
 }
 
 #[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
@@ -71,12 +399,7 @@ pub struct Weapon {
 
 impl Weapon {
     pub fn null_weapon() -> Self {
-        let null_item = Item {
-            id: dec!(0),
-            version: dec!(1),
-            weight: dec!(0),
-            level: 0,
-        };
+        let null_item = Item::null_item();
         Self { 
             class: 0,
             item_info: null_item,
@@ -89,6 +412,31 @@ impl Weapon {
             range: dec!(1), 
         }
     }
+    // Averages the stats of two weapons, then multiplies them. The ability is taken from the first weapon which has one
+    pub fn blend(&self, other: &Weapon, multiplier: Decimal) -> Self {
+        let ability_source = if self.ability != dec!(0) { self } else { other };
+        Self {
+            class: self.class,
+            item_info: self.item_info.clone(),
+            physical_base: (self.physical_base + other.physical_base) / dec!(2) * multiplier,
+            physical_scaling: (self.physical_scaling + other.physical_scaling) / dec!(2) * multiplier,
+            spell_base: (self.spell_base + other.spell_base) / dec!(2) * multiplier,
+            spell_scaling: (self.spell_scaling + other.spell_scaling) / dec!(2) * multiplier,
+            ability: ability_source.ability,
+            ability_odds: ability_source.ability_odds,
+            range: self.range,
+        }
+    }
+    // Broken weapons only keep a portion of their stats
+    pub fn effective(mut self, broken_multiplier: Decimal) -> Self {
+        if self.item_info.is_broken() {
+            self.physical_base *= broken_multiplier;
+            self.physical_scaling *= broken_multiplier;
+            self.spell_base *= broken_multiplier;
+            self.spell_scaling *= broken_multiplier;
+        }
+        self
+    }
 }
 
 #[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
@@ -105,14 +453,26 @@ pub struct Armor {
 
 impl Armor {
     pub fn null_armor(name: ArmorNames) -> Self {
-        let null_item = Item {
-            id: dec!(0),
-            version: dec!(1),
-            weight: dec!(0),
-            level: 0,
-        };
+        let null_item = Item::null_item();
         Self {  part: name, health: dec!(0), defense: dec!(1), item_info: null_item}
     }
+    // Averages the stats of two armor pieces, then multiplies them. Defense is a multiplier, so only the bonus above 1 is scaled
+    pub fn blend(&self, other: &Armor, multiplier: Decimal) -> Self {
+        Self {
+            item_info: self.item_info.clone(),
+            part: self.part,
+            health: (self.health + other.health) / dec!(2) * multiplier,
+            defense: dec!(1) + ((self.defense + other.defense) / dec!(2) - dec!(1)) * multiplier,
+        }
+    }
+    // Broken armor only keeps a portion of its stats. Defense is a multiplier, so only the bonus above 1 is reduced
+    pub fn effective(mut self, broken_multiplier: Decimal) -> Self {
+        if self.item_info.is_broken() {
+            self.health *= broken_multiplier;
+            self.defense = dec!(1) + (self.defense - dec!(1)) * broken_multiplier;
+        }
+        self
+    }
 }
 
 #[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
@@ -135,15 +495,33 @@ pub struct Accessory {
 
 impl Accessory {
     pub fn null_accessory(name: AccessoryNames) -> Self {
-        let null_item = Item {
-            id: dec!(0),
-            version: dec!(1),
-            weight: dec!(0),
-            level: 0,
-        };
+        let null_item = Item::null_item();
         Self { part: name, attack: dec!(1), magic: dec!(1), speed: dec!(0), 
             item_info: null_item, ability: dec!(0), ability_odds: dec!(0)}
     }
+    // Averages the stats of two accessories, then multiplies them. The ability is taken from the first accessory which has one.
+    // Attack and magic are multipliers, so only the bonus above 1 is scaled
+    pub fn blend(&self, other: &Accessory, multiplier: Decimal) -> Self {
+        let ability_source = if self.ability != dec!(0) { self } else { other };
+        Self {
+            item_info: self.item_info.clone(),
+            part: self.part,
+            attack: dec!(1) + ((self.attack + other.attack) / dec!(2) - dec!(1)) * multiplier,
+            magic: dec!(1) + ((self.magic + other.magic) / dec!(2) - dec!(1)) * multiplier,
+            speed: (self.speed + other.speed) / dec!(2) * multiplier,
+            ability: ability_source.ability,
+            ability_odds: ability_source.ability_odds,
+        }
+    }
+    // Broken accessories only keep a portion of their stats. Attack and magic are multipliers, so only the bonus above 1 is reduced
+    pub fn effective(mut self, broken_multiplier: Decimal) -> Self {
+        if self.item_info.is_broken() {
+            self.attack = dec!(1) + (self.attack - dec!(1)) * broken_multiplier;
+            self.magic = dec!(1) + (self.magic - dec!(1)) * broken_multiplier;
+            self.speed *= broken_multiplier;
+        }
+        self
+    }
 }
 
 #[derive(TypeId, Decode, Encode, Describe, Copy, Clone)]
@@ -152,6 +530,8 @@ pub struct CombatInfo {
     pub damage: Decimal,
     pub defense: Decimal,
     pub speed: Decimal,
+    // % of damage dealt which is healed
+    pub lifesteal: Decimal,
     // Decimals go <(Ability #, Ability odds, Ability damage)>
     pub ability_weapon: Option<(Decimal,Decimal,Decimal)>,
     pub ability_gloves: Option<(Decimal,Decimal,Decimal)>,
@@ -171,7 +551,7 @@ pub struct EnemyFullInfo {
     pub exp_on_loss: u128,
     pub exp_on_win: u128,
 }
-#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq, Debug)]
 pub enum Stats {
     Health,
     Attack,
@@ -179,6 +559,14 @@ pub enum Stats {
     Defense,
     Speed,
 }
+// Items can only be fused with items of the same weapon class or gear part
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum FusionGroup {
+    Weapon(u64),
+    Armor(ArmorNames),
+    Accessory(AccessoryNames),
+}
+
 #[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
 pub enum ArmorNames {
     Helmet,
@@ -192,26 +580,171 @@ pub enum AccessoryNames {
     Shoes,
 }
 
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum AffixKind {
+    Prefix,
+    Suffix,
+}
+// All Modifiers are percentages, e.g. Speed 5 is +5% speed
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum Modifier {
+    Health,
+    Attack,
+    Magic,
+    Defense,
+    Speed,
+    Lifesteal,
+    GoldFind,
+    ExpFind,
+}
+
+// What happens when a fusion fails
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum FusionFailure {
+    // Both items are burned for a single item one level lower (minimum level 1)
+    Downgrade,
+    // One of the items is burned, the other is returned unchanged
+    LoseOne,
+}
+
 #[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
 pub enum Categories {
     Weapon,
     Armor,
     Accessory,
+    Skin,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum SkinTarget {
+    Character,
+    Weapon,
+    Armor,
+    Accessory,
+}
+
+// Actions counted on each character for achievements
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum Counter {
+    StagesCleared,
+    EnemiesDefeated,
+    Fusions,
+    Crafts,
+    Sales,
+    Purchases,
+    // Not recorded yet, for when PvP is added. Achievements on it can't unlock until then
+    PvpWins,
+}
+
+// Actions a character takes, recorded for achievements and quests
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum Task {
+    // Enemies defeated on the stage
+    DefeatEnemies(u64),
+    ClearStage(u64),
+    CraftWeapon,
+    CraftArmor(ArmorNames),
+    CraftAccessory(AccessoryNames),
+    CraftGem,
+    Fuse,
+    Sell,
+    Buy,
+}
+
+impl Task {
+    // Achievement counter the task counts towards
+    pub fn counter(&self) -> Counter {
+        match self {
+            Task::DefeatEnemies(_) => Counter::EnemiesDefeated,
+            Task::ClearStage(_) => Counter::StagesCleared,
+            Task::CraftWeapon | Task::CraftArmor(_) | Task::CraftAccessory(_) | Task::CraftGem => Counter::Crafts,
+            Task::Fuse => Counter::Fusions,
+            Task::Sell => Counter::Sales,
+            Task::Buy => Counter::Purchases,
+        }
+    }
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub enum Goal {
+    // Counter reaches the amount
+    Count(Counter, u64),
+    // Character reaches the level
+    Level(u64),
+}
+
+// Points a recipe at the item data table its output is made from
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, PartialEq)]
+pub enum ItemTemplate {
+    Weapon(u64, Decimal),
+    Armor(ArmorNames, Decimal),
+    Accessory(AccessoryNames, Decimal),
+    Gem(Decimal),
 }
 
 #[derive(TypeId, Decode, Encode, Describe, Clone)]
-pub struct SharedData {
-    pub gold_cost: Decimal,
-    pub resource_1: ResourceAddress,
-    pub resource_2: ResourceAddress,
-    pub resource_1_cost: Decimal,
-    pub resource_2_cost: Decimal,
-    pub weight: Decimal,
+pub struct Recipe {
+    // (Resource, amount) burned on craft. Any number of inputs can be used.
+    pub inputs: Vec<(ResourceAddress, Decimal)>,
+    // One of each catalyst is burned on craft
+    pub catalysts: Vec<ResourceAddress>,
+    pub output: ItemTemplate,
+    // Stat rolls go from min/100 to max/100
+    pub min_stat_roll: u128,
+    pub max_stat_roll: u128,
+    // Recipe can only be crafted by burning a scroll of it, which drops from loot tables
+    pub needs_scroll: bool,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub enum Loot {
+    Gold,
+    Greavite,
+    Wood,
+    Catalyst,
+    // Gear minted with stat rolls from min/100 to max/100
+    Item(ItemTemplate, u128, u128),
+    RecipeScroll(u64),
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct LootEntry {
+    pub loot: Loot,
+    // Chance out of 10000, so rare drops can be below 1%
+    pub odds: u128,
+    // Amount dropped is rolled from min to max
+    pub min: u64,
+    pub max: u64,
+}
+
+// Rolled for each enemy fought, on top of the fixed rewards of the enemy
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct LootTable {
+    pub on_win: Vec<LootEntry>,
+    pub on_loss: Vec<LootEntry>,
+}
+
+// What a character needs to use an item
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct Requirements {
+    // Classes which can use the item. If empty, every class can use it (weapons default to their own class)
+    pub classes: Vec<u64>,
+    pub min_level: u64,
+    pub min_stat: Option<(Stats, Decimal)>,
 }
 
 #[derive(TypeId, Decode, Encode, Describe, Clone)]
 pub struct WeaponData {
-    pub crafting_data: SharedData,
+    pub weight: Decimal,
     pub min_rng_roll: u128,
     pub max_rng_roll: u128,
     pub success_number: u128,
@@ -222,20 +755,22 @@ pub struct WeaponData {
     pub magic_base: Decimal,
     pub magic_scaling: Decimal,
     pub range: Decimal,
+    pub requirements: Requirements,
 }
 
 #[derive(TypeId, Decode, Encode, Describe, Clone)]
 pub struct ArmorData {
-    pub crafting_data: SharedData,
+    pub weight: Decimal,
     pub health: Decimal,
     pub health_bonus: Decimal,
     pub defense: Decimal,
     pub defense_bonus: Decimal,
+    pub requirements: Requirements,
 }
 
 #[derive(TypeId, Decode, Encode, Describe, Clone)]
 pub struct AccessoryData {
-    pub crafting_data: SharedData,
+    pub weight: Decimal,
     pub min_rng_roll: u128,
     pub max_rng_roll: u128,
     pub success_number: u128,
@@ -247,6 +782,167 @@ pub struct AccessoryData {
     pub magic_bonus: Decimal,
     pub speed: Decimal,
     pub speed_bonus: Decimal,
+    pub requirements: Requirements,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct RarityData {
+    pub rarity: Rarity,
+    // Odds of the tier are weight / sum of all weights
+    pub weight: u128,
+    pub stat_multiplier: Decimal,
+    pub affix_slots: u64,
+    // Scales repair costs and salvage returns of items with this rarity
+    pub value_multiplier: Decimal,
+    // Sockets of a level 1 item with this rarity
+    pub socket_slots: u64,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct AffixData {
+    pub kind: AffixKind,
+    pub modifier: Modifier,
+    // (min, max) value for each tier. Tier used is the item level, capped at the last tier
+    pub tiers: Vec<(u128, u128)>,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct GemData {
+    pub stat: Stats,
+    pub value: Decimal,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct SocketData {
+    // Items gain a socket every levels_per_socket levels, up to max_sockets
+    pub levels_per_socket: u64,
+    pub max_sockets: u64,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct SetData {
+    // Gear in the set, by category and item id. Armor/Accessory ids match every part with that id
+    pub pieces: Vec<(Categories, Decimal)>,
+    // (Pieces worn, modifier, value). Every bonus with enough pieces worn is applied
+    pub bonuses: Vec<(u64, Modifier, u128)>,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct EncumbranceData {
+    // Carry capacity = base + per level * character level
+    pub base_capacity: Decimal,
+    pub capacity_per_level: Decimal,
+    // (Load, speed multiplier) points sorted by load, where load = gear weight / carry capacity
+    pub curve: Vec<(Decimal, Decimal)>,
+    // Lowest speed multiplier possible
+    pub floor: Decimal,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct AchievementData {
+    pub goal: Goal,
+    // Title added to the character when unlocked
+    pub title: String,
+    // One-time rewards, claimed with claim_achievement
+    pub gold_reward: Decimal,
+    pub skin_reward: Option<Skin>,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct QuestData {
+    // Quest is done once the task is recorded amount times within a window
+    pub task: Task,
+    pub amount: u64,
+    // Progress resets every reset_epochs epochs, e.g. a day or a week worth of epochs
+    pub reset_epochs: u64,
+    pub gold_reward: Decimal,
+    pub greavite_reward: Decimal,
+    pub wood_reward: Decimal,
+    pub exp_reward: u128,
+}
+
+// Shares of each marketplace sale, e.g. .05 is 5%. The seller gets the rest
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct MarketFees {
+    pub burn_share: Decimal,
+    // Sent to the fee vault, which the developer can withdraw
+    pub treasury_share: Decimal,
+    // Paid to the crafter of the item, if it has one
+    pub royalty_share: Decimal,
+}
+
+// Receipt of an order on an order book. Amounts are in the material, and prices in gold per unit
+#[derive(NonFungibleData, TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct OrderReceipt {
+    pub side: Side,
+    pub price: Decimal,
+    pub amount: Decimal,
+    // Amount not filled yet
+    #[scrypto(mutable)]
+    pub remaining: Decimal,
+    // Tokens still held for the order. Material for sell orders, and gold for buy orders
+    #[scrypto(mutable)]
+    pub escrow: Decimal,
+    // Unclaimed tokens from fills. Gold for sell orders, and material for buy orders
+    #[scrypto(mutable)]
+    pub proceeds: Decimal,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Copy, Clone, PartialEq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+// Resting order on an order book. The rest of its state is on the receipt
+#[derive(TypeId, Decode, Encode, Describe, Clone, PartialEq)]
+pub struct BookOrder {
+    pub id: NonFungibleId,
+    pub price: Decimal,
+    // Placement order, so older orders fill first at the same price
+    pub sequence: u64,
+}
+
+// Open amount at each price of an order book, best price first
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct BookDepth {
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+    pub last_price: Option<Decimal>,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct DurabilityData {
+    pub max_durability: u64,
+    // Stat multiplier of gear with 0 durability
+    pub broken_multiplier: Decimal,
+    // Repair costs per point of durability, multiplied by item level and rarity value
+    pub gold_per_point: Decimal,
+    pub greavite_per_point: Decimal,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct SalvageData {
+    // Materials returned per item level, multiplied by rarity value
+    pub greavite: Decimal,
+    pub wood: Decimal,
+    pub gold: Decimal,
+    // % chance for each salvaged item to return a fusion catalyst
+    pub catalyst_odds: u128,
+}
+
+#[derive(TypeId, Decode, Encode, Describe, Clone)]
+pub struct FusionData {
+    // % chance of success, before catalysts
+    pub success_odds: u128,
+    // % chance added by each catalyst used
+    pub catalyst_bonus: u128,
+    pub max_catalysts: u64,
+    // Stats of the fused item are multiplied by a roll from min/100 to max/100
+    pub min_multiplier: u128,
+    pub max_multiplier: u128,
+    pub gold_cost: Decimal,
+    pub failure: FusionFailure,
 }
 
 #[derive(TypeId, Decode, Encode, Describe, Clone)]
@@ -264,4 +960,24 @@ pub struct GameData {
     pub weapon_data: HashMap<(u64, Decimal), WeaponData>,
     pub armor_data: HashMap<(ArmorNames, Decimal), ArmorData>,
     pub accessory_data: HashMap<(AccessoryNames, Decimal), AccessoryData>,
+    pub recipes: HashMap<u64, Recipe>,
+    pub rarity_data: Vec<RarityData>,
+    pub affix_pool: HashMap<u64, AffixData>,
+    pub durability_data: DurabilityData,
+    pub salvage_data: HashMap<Categories, SalvageData>,
+    pub fusion_data: HashMap<u64, FusionData>,
+    pub gem_data: HashMap<Decimal, GemData>,
+    pub socket_data: SocketData,
+    pub set_data: HashMap<u64, SetData>,
+    pub encumbrance_data: EncumbranceData,
+    pub achievement_data: HashMap<u64, AchievementData>,
+    // Quest board
+    pub quest_data: HashMap<u64, QuestData>,
+    // Keyed by (stage, enemy 0-2)
+    pub loot_tables: HashMap<(u64, u64), LootTable>,
+    // Bids placed this many epochs before an auction ends extend it to this many epochs from the bid
+    pub auction_extension: u64,
+    // Tokens marketplace prices can be set in
+    pub payment_tokens: Vec<ResourceAddress>,
+    pub market_fees: MarketFees,
 }